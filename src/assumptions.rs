@@ -0,0 +1,92 @@
+use crate::csp::*;
+
+pub type CellAssumption = (Point, BoardCell);
+
+#[derive(Debug, Clone)]
+pub enum AssumptionResult {
+    Sat(Assignment),
+    // indices of a minimal subset of the assumptions that can't hold together, empty if the
+    // puzzle has no solution at all
+    Unsat(Vec<usize>),
+}
+
+impl CSP {
+    // Searches for a solution in which every assumed cell has the assumed sign, fails if an
+    // assumed cell is not on the board
+    pub fn solve_with_assumptions(&mut self, assumptions: &[CellAssumption]) -> Result<AssumptionResult, String> {
+        for (point, _) in assumptions {
+            if point.row >= self.row_size || point.col >= self.col_size {
+                return Err(format!(
+                    "The assumed cell ({}, {}) is outside the {}x{} board",
+                    point.row, point.col, self.row_size, self.col_size
+                ));
+            }
+        }
+        let all: Vec<usize> = (0..assumptions.len()).collect();
+        if let Some(solution) = self.solve_assuming(assumptions, &all) {
+            return Ok(AssumptionResult::Sat(solution));
+        }
+
+        // deletion based shrinking: drop every assumption that is not needed for the conflict
+        let mut core = all;
+        let mut i = 0;
+        while i < core.len() {
+            let mut candidate = core.clone();
+            candidate.remove(i);
+            if self.solve_assuming(assumptions, &candidate).is_none() {
+                core = candidate;
+            } else {
+                i += 1;
+            }
+        }
+        Ok(AssumptionResult::Unsat(core))
+    }
+
+    fn solve_assuming(&mut self, assumptions: &[CellAssumption], active: &[usize]) -> Option<Assignment> {
+        let mut domain = self.initial_domain();
+        for index in active {
+            let (point, cell) = &assumptions[*index];
+            if !self.restrict_domain(&mut domain, point, cell) {
+                return None;
+            }
+        }
+        self.solutions_from(domain, Some(1)).next()
+    }
+
+    // Restricts the domain of the variable owning the cell to the values that give the cell the
+    // assumed sign, returns false if no value can. The cell must be on the board.
+    pub fn restrict_domain(&self, domain: &mut Domain, point: &Point, cell: &BoardCell) -> bool {
+        let variable = match self.variables.get(self.board_variable_association[point.row][point.col]) {
+            Some(variable) if variable.poles.iter().any(|pole| pole.row == point.row && pole.col == point.col) => variable,
+            // a cell outside every magnet slot is always empty
+            _ => return *cell == BoardCell::Empty || *cell == BoardCell::Unassigned,
+        };
+
+        let pole_number = CSP::get_pole_number(variable, point);
+        let allowed = match (cell, pole_number) {
+            (BoardCell::Positive, 0) | (BoardCell::Negative, 1) => Value::Pole1PositivePole2Negative,
+            (BoardCell::Positive, _) | (BoardCell::Negative, _) => Value::Pole2PositivePole1Negative,
+            (BoardCell::Empty, _) => Value::Empty,
+            (BoardCell::Unassigned, _) => return true,
+        };
+        domain[variable.index].retain(|value| *value == allowed);
+        !domain[variable.index].is_empty()
+    }
+}
+
+// Parses an assumption written as row,col,sign where sign is +, - or 0 for a blank cell
+pub fn parse_assumption(text: &str) -> Result<CellAssumption, String> {
+    let parts: Vec<&str> = text.split(',').map(|part| part.trim()).collect();
+    if parts.len() != 3 {
+        return Err(format!("Assumption '{}' must be written as row,col,sign", text));
+    }
+    let row = parts[0].parse::<usize>().map_err(|e| format!("Bad row in '{}': {}", text, e))?;
+    let col = parts[1].parse::<usize>().map_err(|e| format!("Bad column in '{}': {}", text, e))?;
+    let cell = match parts[2] {
+        "+" => BoardCell::Positive,
+        "-" => BoardCell::Negative,
+        "0" | "." | "blank" => BoardCell::Empty,
+        sign => return Err(format!("Unknown sign '{}', expected +, - or 0", sign)),
+    };
+    Ok((Point { row, col }, cell))
+}
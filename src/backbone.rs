@@ -0,0 +1,110 @@
+use crate::csp::*;
+use crate::png::*;
+use crate::render::*;
+
+const FREE_COLOR: Rgb = [225, 225, 225];
+const VALUES: [Value; 3] = [
+    Value::Pole1PositivePole2Negative,
+    Value::Pole2PositivePole1Negative,
+    Value::Empty,
+];
+
+#[derive(Debug, Clone)]
+pub struct Backbone {
+    // for every variable, the values it takes in at least one solution
+    pub supported_values: Vec<Vec<Value>>,
+}
+
+impl Backbone {
+    // The value of a variable if it is the same in every solution
+    pub fn forced_value(&self, var_index: VariableIndex) -> Option<Value> {
+        match self.supported_values[var_index][..] {
+            [value] => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn forced_count(&self) -> usize {
+        (0..self.supported_values.len())
+            .filter(|var_index| self.forced_value(*var_index).is_some())
+            .count()
+    }
+
+    // Board with the forced cells filled in and the free cells left unassigned
+    pub fn forced_board(&self, csp: &CSP) -> Vec<Vec<BoardCell>> {
+        let assignment: Assignment = (0..self.supported_values.len())
+            .map(|var_index| self.forced_value(var_index).unwrap_or(Value::Unassigned))
+            .collect();
+        csp.board_from_assignment(&assignment)
+    }
+}
+
+impl CSP {
+    // Finds the values every variable can take over all solutions, returns None if there is no
+    // solution. Instead of enumerating every solution, each value not yet seen in a solution is
+    // tested with one search whose initial domains pin the variable to that value.
+    pub fn backbone(&mut self) -> Option<Backbone> {
+        let mut supported_values: Vec<Vec<Value>> = vec![Vec::new(); self.variables.len()];
+        let first = self.solutions(Some(1)).next()?;
+        Self::mark_supported(&mut supported_values, &first);
+
+        for var_index in 0..self.variables.len() {
+            for value in VALUES {
+                if supported_values[var_index].contains(&value) {
+                    continue;
+                }
+                let mut domain = self.initial_domain();
+                domain[var_index] = vec![value];
+                if let Some(solution) = self.solutions_from(domain, Some(1)).next() {
+                    Self::mark_supported(&mut supported_values, &solution);
+                }
+            }
+        }
+        for values in supported_values.iter_mut() {
+            values.sort_by_key(|value| VALUES.iter().position(|v| v == value));
+        }
+        Some(Backbone { supported_values })
+    }
+
+    fn mark_supported(supported_values: &mut [Vec<Value>], solution: &Assignment) {
+        for (values, value) in supported_values.iter_mut().zip(solution) {
+            if !values.contains(value) {
+                values.push(*value);
+            }
+        }
+    }
+
+    pub fn print_backbone(&self, backbone: &Backbone) {
+        self.print_cells(&backbone.forced_board(self));
+        println!();
+        println!(
+            "{} of {} magnets are forced, cells marked with * differ between solutions",
+            backbone.forced_count(),
+            self.variables.len()
+        );
+    }
+
+    // Forced cells are drawn as usual, free cells are shaded and marked with a question mark
+    pub fn render_backbone(&self, backbone: &Backbone) -> Canvas {
+        let board = backbone.forced_board(self);
+        let owners = self.cell_owners();
+        let is_free = |i: usize, j: usize| board[i][j] == BoardCell::Unassigned && owners[i][j].is_some();
+        let free = |i: usize, j: usize| {
+            if is_free(i, j) {
+                Some(FREE_COLOR)
+            } else {
+                None
+            }
+        };
+        let mut canvas = self.render_board_with(&board, &free);
+        for i in 0..self.row_size {
+            for j in 0..self.col_size {
+                if is_free(i, j) {
+                    let (x, y) = self.cell_origin(i, j);
+                    canvas.draw_text(x + CELL_SIZE / 2, y + CELL_SIZE / 2, "?", 2, BLACK);
+                }
+            }
+        }
+        canvas
+    }
+}
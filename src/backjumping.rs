@@ -0,0 +1,126 @@
+use crate::clues::Clue;
+use crate::csp::*;
+use std::collections::VecDeque;
+
+// For every variable, the assigned variables blamed for the values missing from its domain
+pub type Explanations = Vec<Vec<VariableIndex>>;
+
+impl CSP {
+    // The same propagation as inference, but every revision is blamed on the assigned variables it
+    // depends on, which join the explanation of the revised variable. On a wipeout the explanation
+    // of the emptied variable is returned as the conflict set. The domains and the explanations
+    // are both changed in place, and every change is recorded on the trail.
+    pub fn explained_inference(
+        &self,
+        var_index: usize,
+        domains: &mut Domain,
+        explanations: &mut Explanations,
+        trail: &mut Trail,
+        assignment: &Assignment,
+    ) -> Result<(), Vec<VariableIndex>> {
+        if self.inference_mode == InferenceMode::None {
+            return Ok(());
+        }
+
+        let mut arc_queue: VecDeque<ConstraintArc> = VecDeque::new();
+        self.generate_arc_constraints(var_index, assignment, &mut arc_queue, var_index);
+        while let Some(constraint_arc) = arc_queue.pop_front() {
+            let (feasible, revised) = self.revise(&constraint_arc, domains, trail, assignment);
+            if feasible && !revised {
+                continue;
+            }
+            for culprit in self.arc_culprits(&constraint_arc, explanations, assignment) {
+                if !explanations[constraint_arc.xi].contains(&culprit) {
+                    explanations[constraint_arc.xi].push(culprit);
+                    trail.push(TrailEntry::Blame(constraint_arc.xi));
+                }
+            }
+            if !feasible {
+                return Err(explanations[constraint_arc.xi].clone());
+            }
+            if self.inference_mode == InferenceMode::MAC {
+                self.generate_arc_constraints(constraint_arc.xi, assignment, &mut arc_queue, constraint_arc.xj);
+            }
+        }
+        Ok(())
+    }
+
+    // Assigned variables that revising the arc depends on: xj and whatever explains its domain, and
+    // for a limit based arc the magnets already placed in the line the two poles share
+    fn arc_culprits(&self, constraint_arc: &ConstraintArc, explanations: &Explanations, assignment: &Assignment) -> Vec<VariableIndex> {
+        let mut culprits = explanations[constraint_arc.xj].clone();
+        if assignment[constraint_arc.xj] != Value::Unassigned {
+            blame(&mut culprits, constraint_arc.xj);
+        }
+        if let Constraint::LimitBased(xi_pole_index, xj_pole_index) = constraint_arc.constraint {
+            let xi_pole = &self.variables[constraint_arc.xi].poles[xi_pole_index as usize];
+            let xj_pole = &self.variables[constraint_arc.xj].poles[xj_pole_index as usize];
+            let line = if xi_pole.row == xj_pole.row {
+                Some(Clue::RowPositive(xi_pole.row))
+            } else if xi_pole.col == xj_pole.col {
+                Some(Clue::ColPositive(xi_pole.col))
+            } else {
+                None
+            };
+            if let Some(line) = line {
+                for culprit in self.placed_in_line(line, constraint_arc.xi, assignment) {
+                    blame(&mut culprits, culprit);
+                }
+            }
+        }
+        culprits
+    }
+
+    // Assigned variables that make the value of the variable fail is_consistent: the magnets with a
+    // like pole next to one of its poles, and the magnets in every line whose clue is now broken
+    pub fn inconsistency_culprits(&self, var_index: VariableIndex, assignment: &Assignment) -> Vec<VariableIndex> {
+        let mut culprits = Vec::new();
+        let variable = &self.variables[var_index];
+        let mut lines = Vec::new();
+        for (pole, other_pole) in [(&variable.poles[0], &variable.poles[1]), (&variable.poles[1], &variable.poles[0])] {
+            let sign = &self.board[pole.row][pole.col];
+            if *sign == BoardCell::Positive || *sign == BoardCell::Negative {
+                for cell in self.touching_cells(pole, other_pole) {
+                    if self.board[cell.row][cell.col] == *sign {
+                        blame(&mut culprits, self.board_variable_association[cell.row][cell.col]);
+                    }
+                }
+            }
+            for clue in [Clue::RowPositive(pole.row), Clue::RowNegative(pole.row), Clue::ColPositive(pole.col), Clue::ColNegative(pole.col)] {
+                if !lines.contains(&clue) {
+                    lines.push(clue);
+                }
+            }
+        }
+        for clue in lines {
+            let (count, value) = (self.current_count(clue), self.clue_value(clue));
+            let (cells, _) = self.clue_cells(clue);
+            let complete = cells.iter().all(|cell| self.board[cell.row][cell.col] != BoardCell::Unassigned);
+            if CSP::exceeds_clue(count, value) || (complete && CSP::misses_clue(count, value)) {
+                for culprit in self.placed_in_line(clue, var_index, assignment) {
+                    blame(&mut culprits, culprit);
+                }
+            }
+        }
+        culprits
+    }
+
+    // Assigned variables other than the given one with a pole in the clue's line
+    fn placed_in_line(&self, clue: Clue, except: VariableIndex, assignment: &Assignment) -> Vec<VariableIndex> {
+        let mut placed = Vec::new();
+        for cell in self.clue_cells(clue).0 {
+            let var_index = self.board_variable_association[cell.row][cell.col];
+            if var_index != except && assignment[var_index] != Value::Unassigned {
+                blame(&mut placed, var_index);
+            }
+        }
+        placed
+    }
+}
+
+// Adds the variable to a conflict set or explanation unless it is already there
+pub fn blame(culprits: &mut Vec<VariableIndex>, var_index: VariableIndex) {
+    if !culprits.contains(&var_index) {
+        culprits.push(var_index);
+    }
+}
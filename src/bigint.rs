@@ -0,0 +1,136 @@
+use std::fmt;
+
+// Arbitrary precision unsigned integer, only supports what exact model counting needs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BigUint {
+    // base 2^32 digits, least significant first, no trailing zero digits
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn zero() -> BigUint {
+        BigUint { limbs: Vec::new() }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn add_assign(&mut self, other: &BigUint) {
+        let mut carry = 0u64;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            if i == self.limbs.len() {
+                self.limbs.push(0);
+            }
+            let sum = self.limbs[i] as u64 + *other.limbs.get(i).unwrap_or(&0) as u64 + carry;
+            self.limbs[i] = sum as u32;
+            carry = sum >> 32;
+        }
+        if carry != 0 {
+            self.limbs.push(carry as u32);
+        }
+    }
+
+    // Divides in place by a small divisor and returns the remainder
+    fn div_rem_small(&mut self, divisor: u32) -> u32 {
+        let mut remainder = 0u64;
+        for limb in self.limbs.iter_mut().rev() {
+            let current = (remainder << 32) | *limb as u64;
+            *limb = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        remainder as u32
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(value: u64) -> BigUint {
+        let mut limbs = vec![value as u32, (value >> 32) as u32];
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigUint { limbs }
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // peel off groups of 9 decimal digits
+        let mut value = self.clone();
+        let mut groups = Vec::new();
+        while !value.is_zero() {
+            groups.push(value.div_rem_small(1_000_000_000));
+        }
+        write!(f, "{}", groups[groups.len() - 1])?;
+        for group in groups.iter().rev().skip(1) {
+            write!(f, "{:09}", group)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(value: u128) -> BigUint {
+        let mut limbs: Vec<u32> = (0..4).map(|i| (value >> (32 * i)) as u32).collect();
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigUint { limbs }
+    }
+
+    // values around the limb boundaries and a few large ones
+    fn samples() -> Vec<u128> {
+        let mut samples = vec![0, 1, 9, 10, 999_999_999, 1_000_000_000, u32::MAX as u128, 1 << 32, u64::MAX as u128, 1 << 64];
+        let mut state: u128 = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..50 {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            samples.push(state >> (state % 64));
+        }
+        samples
+    }
+
+    #[test]
+    fn from_u64_and_display_match_u128() {
+        for value in samples() {
+            let value = value as u64;
+            assert_eq!(BigUint::from(value), big(value as u128));
+            assert_eq!(BigUint::from(value).to_string(), value.to_string());
+        }
+        for value in samples() {
+            assert_eq!(big(value).to_string(), value.to_string());
+        }
+    }
+
+    #[test]
+    fn add_assign_matches_u128() {
+        for a in samples() {
+            for b in samples() {
+                let (a, b) = (a >> 1, b >> 1);
+                let mut sum = big(a);
+                sum.add_assign(&big(b));
+                assert_eq!(sum, big(a + b));
+            }
+        }
+    }
+
+    #[test]
+    fn div_rem_small_matches_u128() {
+        for value in samples() {
+            for divisor in [1, 2, 7, 10, 1_000_000_000, u32::MAX] {
+                let mut quotient = big(value);
+                let remainder = quotient.div_rem_small(divisor);
+                assert_eq!(quotient, big(value / divisor as u128));
+                assert_eq!(remainder as u128, value % divisor as u128);
+            }
+        }
+    }
+}
@@ -0,0 +1,536 @@
+use crate::restarts::luby;
+
+// A literal as written in DIMACS: the variable number from 1, negative when negated
+pub type Lit = i32;
+
+// A line of a DRAT proof: a clause implied by the formula and the clauses added before, or a
+// clause no longer needed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofStep {
+    Add(Vec<Lit>),
+    Delete(Vec<Lit>),
+}
+
+// Conflicts in the first run before a restart, later runs follow the Luby sequence
+const RESTART_UNIT: usize = 100;
+const ACTIVITY_DECAY: f64 = 0.95;
+// Conflicts before the learnt clauses are reduced for the first time, and how much later each
+// further reduction comes
+const FIRST_REDUCTION: usize = 2000;
+const REDUCTION_INCREMENT: usize = 300;
+
+// A small conflict driven clause learning SAT solver: two watched literals, first UIP learning
+// with backjumping, VSIDS variable activities, phase saving and Luby restarts. Clauses can be
+// added between calls to solve, which keeps everything learned so far.
+pub struct Cdcl {
+    // literals are stored as 2 * variable + 1 when negated, variables from 0
+    clauses: Vec<Vec<usize>>,
+    // clauses watching each literal, visited when the literal becomes false
+    watches: Vec<Vec<usize>>,
+    // 1 true, -1 false, 0 unassigned
+    values: Vec<i8>,
+    levels: Vec<usize>,
+    // clause that implied the value, None for decisions and top level facts
+    reasons: Vec<Option<usize>>,
+    trail: Vec<usize>,
+    // trail position where each decision level starts
+    level_starts: Vec<usize>,
+    propagated: usize,
+    activity: Vec<f64>,
+    increment: f64,
+    // value each variable had last, tried first when it is decided again
+    phase: Vec<bool>,
+    heap: ActivityHeap,
+    // learnt clauses with the number of decision levels of their literals
+    learnts: Vec<(usize, usize)>,
+    // conflicts at which the learnt clauses are reduced next
+    next_reduction: usize,
+    reductions: usize,
+    // an empty clause was added or derived at the top level
+    unsat: bool,
+    // clauses learnt and deleted, only recorded once enable_proof was called
+    pub proof: Option<Vec<ProofStep>>,
+    pub decisions: usize,
+    pub conflicts: usize,
+    pub propagations: usize,
+    pub restarts: usize,
+}
+
+// Unassigned variables ordered by activity, largest first
+struct ActivityHeap {
+    items: Vec<usize>,
+    // position of each variable in items, None when not in the heap
+    positions: Vec<Option<usize>>,
+}
+
+impl Cdcl {
+    pub fn new(num_vars: usize) -> Cdcl {
+        let mut heap = ActivityHeap {
+            items: Vec::new(),
+            positions: vec![None; num_vars],
+        };
+        let activity = vec![0.0; num_vars];
+        for var in 0..num_vars {
+            heap.insert(var, &activity);
+        }
+        Cdcl {
+            clauses: Vec::new(),
+            watches: vec![Vec::new(); 2 * num_vars],
+            values: vec![0; num_vars],
+            levels: vec![0; num_vars],
+            reasons: vec![None; num_vars],
+            trail: Vec::new(),
+            level_starts: Vec::new(),
+            propagated: 0,
+            activity,
+            increment: 1.0,
+            phase: vec![false; num_vars],
+            heap,
+            learnts: Vec::new(),
+            next_reduction: FIRST_REDUCTION,
+            reductions: 0,
+            unsat: false,
+            proof: None,
+            decisions: 0,
+            conflicts: 0,
+            propagations: 0,
+            restarts: 0,
+        }
+    }
+
+    // Records every clause learnt and deleted from now on, so that an unsatisfiable result can be
+    // checked as a DRAT proof against the clauses added
+    pub fn enable_proof(&mut self) {
+        self.proof.get_or_insert_with(Vec::new);
+    }
+
+    pub fn num_vars(&self) -> usize {
+        self.values.len()
+    }
+
+    // Adds a clause of DIMACS literals, only between calls to solve
+    pub fn add_clause(&mut self, clause: &[Lit]) {
+        let mut literals: Vec<usize> = clause.iter().map(|lit| internal(*lit)).collect();
+        literals.sort_unstable();
+        literals.dedup();
+        // a clause with both signs of a variable always holds
+        if literals.windows(2).any(|pair| pair[0] ^ 1 == pair[1]) {
+            return;
+        }
+        if literals.iter().any(|lit| self.value(*lit) == 1) {
+            return;
+        }
+        literals.retain(|lit| self.value(*lit) == 0);
+        match literals.len() {
+            0 => {
+                self.unsat = true;
+                self.log(ProofStep::Add(Vec::new()));
+            }
+            1 => self.enqueue(literals[0], None),
+            _ => {
+                self.attach(literals);
+            }
+        }
+    }
+
+    // Returns a model, indexed by variable from 0, or None when the clauses can't be satisfied
+    pub fn solve(&mut self) -> Option<Vec<bool>> {
+        if self.unsat {
+            return None;
+        }
+        let mut restart = 0;
+        let mut conflicts_since_restart = 0;
+        loop {
+            if let Some(conflict) = self.propagate() {
+                self.conflicts += 1;
+                conflicts_since_restart += 1;
+                if self.level_starts.is_empty() {
+                    self.unsat = true;
+                    self.log(ProofStep::Add(Vec::new()));
+                    return None;
+                }
+                let (learnt, backjump_level) = self.analyze(conflict);
+                self.log(ProofStep::Add(external(&learnt)));
+                self.cancel_until(backjump_level);
+                let asserting = learnt[0];
+                if learnt.len() == 1 {
+                    self.enqueue(asserting, None);
+                } else {
+                    let mut levels: Vec<usize> = learnt.iter().map(|literal| self.levels[literal / 2]).collect();
+                    levels.sort_unstable();
+                    levels.dedup();
+                    let clause_index = self.attach(learnt);
+                    self.learnts.push((clause_index, levels.len()));
+                    self.enqueue(asserting, Some(clause_index));
+                }
+                self.increment /= ACTIVITY_DECAY;
+                if self.conflicts >= self.next_reduction {
+                    self.reduce_learnts();
+                    self.next_reduction = self.conflicts + FIRST_REDUCTION + REDUCTION_INCREMENT * self.reductions;
+                    self.reductions += 1;
+                }
+                continue;
+            }
+            if conflicts_since_restart >= RESTART_UNIT * luby(restart + 1) {
+                self.cancel_until(0);
+                restart += 1;
+                self.restarts += 1;
+                conflicts_since_restart = 0;
+            }
+            match self.pick_branch_variable() {
+                Some(var) => {
+                    self.decisions += 1;
+                    self.level_starts.push(self.trail.len());
+                    let literal = 2 * var + if self.phase[var] { 0 } else { 1 };
+                    self.enqueue(literal, None);
+                }
+                None => {
+                    let model = self.values.iter().map(|value| *value == 1).collect();
+                    self.cancel_until(0);
+                    return Some(model);
+                }
+            }
+        }
+    }
+
+    fn log(&mut self, step: ProofStep) {
+        if let Some(proof) = self.proof.as_mut() {
+            proof.push(step);
+        }
+    }
+
+    fn value(&self, literal: usize) -> i8 {
+        literal_value(&self.values, literal)
+    }
+
+    // Stores a clause of at least two literals, watching the first two, and returns its index
+    fn attach(&mut self, literals: Vec<usize>) -> usize {
+        let clause_index = self.clauses.len();
+        self.watches[literals[0]].push(clause_index);
+        self.watches[literals[1]].push(clause_index);
+        self.clauses.push(literals);
+        clause_index
+    }
+
+    fn enqueue(&mut self, literal: usize, reason: Option<usize>) {
+        let var = literal / 2;
+        self.values[var] = if literal & 1 == 1 { -1 } else { 1 };
+        self.levels[var] = self.level_starts.len();
+        self.reasons[var] = reason;
+        self.trail.push(literal);
+    }
+
+    // Unit propagation over the watched literals, returns the clause that became false if any
+    fn propagate(&mut self) -> Option<usize> {
+        while self.propagated < self.trail.len() {
+            let false_literal = self.trail[self.propagated] ^ 1;
+            self.propagated += 1;
+            self.propagations += 1;
+            let mut watching = std::mem::take(&mut self.watches[false_literal]);
+            let mut kept = 0;
+            let mut conflict = None;
+            let mut position = 0;
+            while position < watching.len() {
+                let clause_index = watching[position];
+                position += 1;
+                let clause = &mut self.clauses[clause_index];
+                // dropped by reduce_learnts
+                if clause.is_empty() {
+                    continue;
+                }
+                if clause[0] == false_literal {
+                    clause.swap(0, 1);
+                }
+                let first = clause[0];
+                if literal_value(&self.values, first) != 1 {
+                    // look for another literal that isn't false to watch instead
+                    if let Some(k) = (2..clause.len()).find(|k| literal_value(&self.values, clause[*k]) != -1) {
+                        clause.swap(1, k);
+                        let new_watch = clause[1];
+                        self.watches[new_watch].push(clause_index);
+                        continue;
+                    }
+                    if literal_value(&self.values, first) == -1 {
+                        conflict = Some(clause_index);
+                    } else {
+                        self.enqueue(first, Some(clause_index));
+                    }
+                }
+                watching[kept] = clause_index;
+                kept += 1;
+                if conflict.is_some() {
+                    break;
+                }
+            }
+            // keep the clauses not visited after a conflict
+            while position < watching.len() {
+                watching[kept] = watching[position];
+                kept += 1;
+                position += 1;
+            }
+            watching.truncate(kept);
+            self.watches[false_literal] = watching;
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    // Drops the less useful half of the learnt clauses, judged by the number of decision levels
+    // they span, except the ones that are the reason of a current value
+    fn reduce_learnts(&mut self) {
+        let mut learnts = std::mem::take(&mut self.learnts);
+        learnts.sort_by_key(|(clause_index, levels)| (std::cmp::Reverse(*levels), std::cmp::Reverse(self.clauses[*clause_index].len())));
+        let half = learnts.len() / 2;
+        for (position, (clause_index, levels)) in learnts.into_iter().enumerate() {
+            let first = self.clauses[clause_index][0];
+            let locked = self.reasons[first / 2] == Some(clause_index) && self.value(first) == 1;
+            if position < half && levels > 2 && !locked {
+                let clause = std::mem::take(&mut self.clauses[clause_index]);
+                self.log(ProofStep::Delete(external(&clause)));
+            } else {
+                self.learnts.push((clause_index, levels));
+            }
+        }
+    }
+
+    // First UIP learning: resolves the conflict clause with the reasons of the literals of the
+    // current level, latest first, until one literal of that level is left. Returns the learnt
+    // clause with that literal first and the literal of the highest other level second, and the
+    // level to jump back to.
+    fn analyze(&mut self, conflict: usize) -> (Vec<usize>, usize) {
+        let current_level = self.level_starts.len();
+        let mut seen = vec![false; self.num_vars()];
+        let mut learnt = vec![0];
+        let mut open = 0;
+        let mut clause_index = conflict;
+        let mut trail_index = self.trail.len();
+        let mut skip_first = false;
+        let uip = loop {
+            let clause = self.clauses[clause_index].clone();
+            for literal in clause.into_iter().skip(if skip_first { 1 } else { 0 }) {
+                let var = literal / 2;
+                if seen[var] || self.levels[var] == 0 {
+                    continue;
+                }
+                seen[var] = true;
+                self.bump(var);
+                if self.levels[var] == current_level {
+                    open += 1;
+                } else {
+                    learnt.push(literal);
+                }
+            }
+            // latest literal of the trail taking part in the conflict
+            loop {
+                trail_index -= 1;
+                if seen[self.trail[trail_index] / 2] {
+                    break;
+                }
+            }
+            let literal = self.trail[trail_index];
+            seen[literal / 2] = false;
+            open -= 1;
+            if open == 0 {
+                break literal;
+            }
+            clause_index = self.reasons[literal / 2].expect("Only decisions have no reason");
+            skip_first = true;
+        };
+        learnt[0] = uip ^ 1;
+
+        let mut backjump_level = 0;
+        if learnt.len() > 1 {
+            let highest = (1..learnt.len()).max_by_key(|index| self.levels[learnt[*index] / 2]).unwrap_or(1);
+            learnt.swap(1, highest);
+            backjump_level = self.levels[learnt[1] / 2];
+        }
+        (learnt, backjump_level)
+    }
+
+    fn cancel_until(&mut self, level: usize) {
+        if self.level_starts.len() <= level {
+            return;
+        }
+        let start = self.level_starts[level];
+        for literal in self.trail.drain(start..).rev() {
+            let var = literal / 2;
+            self.phase[var] = self.values[var] == 1;
+            self.values[var] = 0;
+            self.reasons[var] = None;
+            self.heap.insert(var, &self.activity);
+        }
+        self.level_starts.truncate(level);
+        self.propagated = start;
+    }
+
+    fn pick_branch_variable(&mut self) -> Option<usize> {
+        while let Some(var) = self.heap.pop(&self.activity) {
+            if self.values[var] == 0 {
+                return Some(var);
+            }
+        }
+        None
+    }
+
+    fn bump(&mut self, var: usize) {
+        self.activity[var] += self.increment;
+        if self.activity[var] > 1e100 {
+            for activity in &mut self.activity {
+                *activity *= 1e-100;
+            }
+            self.increment *= 1e-100;
+        }
+        self.heap.increased(var, &self.activity);
+    }
+}
+
+impl ActivityHeap {
+    fn insert(&mut self, var: usize, activity: &[f64]) {
+        if self.positions[var].is_some() {
+            return;
+        }
+        self.positions[var] = Some(self.items.len());
+        self.items.push(var);
+        self.sift_up(self.items.len() - 1, activity);
+    }
+
+    fn pop(&mut self, activity: &[f64]) -> Option<usize> {
+        let top = *self.items.first()?;
+        let last = self.items.pop()?;
+        self.positions[top] = None;
+        if !self.items.is_empty() {
+            self.items[0] = last;
+            self.positions[last] = Some(0);
+            self.sift_down(0, activity);
+        }
+        Some(top)
+    }
+
+    fn increased(&mut self, var: usize, activity: &[f64]) {
+        if let Some(position) = self.positions[var] {
+            self.sift_up(position, activity);
+        }
+    }
+
+    fn sift_up(&mut self, mut position: usize, activity: &[f64]) {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if activity[self.items[parent]] >= activity[self.items[position]] {
+                break;
+            }
+            self.swap(parent, position);
+            position = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut position: usize, activity: &[f64]) {
+        loop {
+            let mut largest = position;
+            for child in [2 * position + 1, 2 * position + 2] {
+                if child < self.items.len() && activity[self.items[child]] > activity[self.items[largest]] {
+                    largest = child;
+                }
+            }
+            if largest == position {
+                break;
+            }
+            self.swap(largest, position);
+            position = largest;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.items.swap(a, b);
+        self.positions[self.items[a]] = Some(a);
+        self.positions[self.items[b]] = Some(b);
+    }
+}
+
+fn internal(lit: Lit) -> usize {
+    2 * (lit.unsigned_abs() as usize - 1) + if lit < 0 { 1 } else { 0 }
+}
+
+fn external(literals: &[usize]) -> Vec<Lit> {
+    literals.iter().map(|literal| (literal / 2 + 1) as Lit * if literal & 1 == 1 { -1 } else { 1 }).collect()
+}
+
+// Writes the steps in the DRAT text format, deletions start with d
+pub fn drat_text(proof: &[ProofStep]) -> String {
+    let mut text = String::new();
+    for step in proof {
+        let (prefix, clause) = match step {
+            ProofStep::Add(clause) => ("", clause),
+            ProofStep::Delete(clause) => ("d ", clause),
+        };
+        text += prefix;
+        for literal in clause {
+            text += &format!("{} ", literal);
+        }
+        text += "0\n";
+    }
+    text
+}
+
+fn literal_value(values: &[i8], literal: usize) -> i8 {
+    let value = values[literal / 2];
+    if literal & 1 == 1 {
+        -value
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // pigeon p sits in hole h, with variables from 1
+    fn pigeonhole(pigeons: usize, holes: usize) -> (usize, Vec<Vec<Lit>>) {
+        let var = |pigeon: usize, hole: usize| (pigeon * holes + hole + 1) as Lit;
+        let mut clauses = Vec::new();
+        for pigeon in 0..pigeons {
+            clauses.push((0..holes).map(|hole| var(pigeon, hole)).collect());
+        }
+        for hole in 0..holes {
+            for first in 0..pigeons {
+                for second in first + 1..pigeons {
+                    clauses.push(vec![-var(first, hole), -var(second, hole)]);
+                }
+            }
+        }
+        (pigeons * holes, clauses)
+    }
+
+    fn solve(num_vars: usize, clauses: &[Vec<Lit>]) -> Option<Vec<bool>> {
+        let mut solver = Cdcl::new(num_vars);
+        for clause in clauses {
+            solver.add_clause(clause);
+        }
+        solver.solve()
+    }
+
+    #[test]
+    fn more_pigeons_than_holes_is_unsatisfiable() {
+        for holes in 1..=5 {
+            let (num_vars, clauses) = pigeonhole(holes + 1, holes);
+            assert_eq!(solve(num_vars, &clauses), None, "{} holes", holes);
+        }
+    }
+
+    #[test]
+    fn empty_clause_is_unsatisfiable() {
+        assert_eq!(solve(2, &[vec![1, 2], vec![]]), None);
+        assert_eq!(solve(1, &[vec![1], vec![-1]]), None);
+    }
+
+    #[test]
+    fn model_satisfies_every_clause() {
+        let (num_vars, clauses) = pigeonhole(5, 5);
+        let model = solve(num_vars, &clauses).expect("As many pigeons as holes fit");
+        for clause in &clauses {
+            assert!(clause.iter().any(|literal| model[literal.unsigned_abs() as usize - 1] == (*literal > 0)), "{:?}", clause);
+        }
+    }
+}
@@ -0,0 +1,467 @@
+use crate::backjumping::Explanations;
+use crate::clues::Clue;
+use crate::csp::*;
+use crate::limits::SolveLimits;
+use crate::rng::Rng;
+use crate::solutions::Solutions;
+use crate::solver::{Frame, Solver};
+
+const HEADER: &str = "ravenous-polarity checkpoint 2";
+
+// A checkpoint is a text file with one keyword per line:
+//   puzzle <rows> <cols> <variables> and the four clue vectors, to refuse resuming another puzzle
+//   mode, backjump, found: inference mode, on or off, and solutions found so far
+//   rng: the random generator state and whether it shuffles the values or only breaks ties
+//   counters: the four curr_*_poles vectors, checked against the replayed assignment
+//   assignment: one value per variable
+//   root: the root domains if the search has not started yet
+//   domains: the current domains once the search has started
+//   explanations: the current explanation of every variable once the search has started, when
+//   backjumping
+//   trail: the changes since the root, oldest first, a pruned value as its value, variable and
+//   the position it had in the domain, like p12@0, and a culprit added to the explanation of a
+//   variable as b and the variable, like b12
+//   frame <var> <next value> <assigned> <values> <trail mark>: one line per depth, from the top
+//   jump <solution below> <conflict set>: after every frame when backjumping, the flag as 0 or 1
+// Sets of variables are written as their variables separated by commas, or _ when empty.
+// Values are written as p (pole 1 positive), n (pole 2 positive), e (empty) and u (unassigned), a
+// domain as the letters of its values, or _ when it is empty.
+impl Solutions<'_> {
+    pub fn checkpoint(&self) -> String {
+        let solver = &self.solver;
+        let csp = &solver.csp;
+        let mut lines = vec![String::from(HEADER)];
+        lines.push(format!("puzzle {} {} {}", csp.row_size, csp.col_size, csp.variables.len()));
+        for clues in [&csp.row_pos_poles, &csp.row_neg_poles, &csp.col_pos_poles, &csp.col_neg_poles] {
+            lines.push(format!("clues {}", join(clues)));
+        }
+        lines.push(format!("mode {}", csp.inference_mode));
+        lines.push(format!("backjump {}", if csp.backjumping { "on" } else { "off" }));
+        lines.push(format!("found {}", self.found));
+        match &solver.rng {
+            Some(rng) => {
+                let usage = if solver.shuffle_values { "shuffle" } else { "ties" };
+                lines.push(format!("rng {} {}", rng.state(), usage));
+            }
+            None => lines.push(String::from("rng none")),
+        }
+        for counters in csp.current_counters() {
+            lines.push(format!("counters {}", join(&counters)));
+        }
+        lines.push(format!("assignment {}", values_text(&solver.assignment)));
+        if let Some(domain) = &solver.initial_domain {
+            lines.push(format!("root {}", domain_text(domain)));
+        }
+        if !solver.stack.is_empty() {
+            lines.push(format!("domains {}", domain_text(&solver.domains)));
+            if csp.backjumping {
+                let explanations: Vec<String> = solver.explanations.iter().map(|set| set_text(set)).collect();
+                lines.push(format!("explanations {}", explanations.join(" ")));
+            }
+            lines.push(format!("trail {}", trail_text(&solver.trail)));
+        }
+        for frame in &solver.stack {
+            lines.push(format!(
+                "frame {} {} {} {} {}",
+                frame.var_index,
+                frame.next_value,
+                frame.assigned.map_or('-', value_char),
+                values_text(&frame.values),
+                frame.trail_mark
+            ));
+            if csp.backjumping {
+                lines.push(format!("jump {} {}", frame.solution_below as u8, set_text(&frame.conflict_set)));
+            }
+        }
+        lines.join("\n") + "\n"
+    }
+
+    // Writes the checkpoint to a temporary file first, so a crash while saving keeps the old one
+    pub fn save_checkpoint(&self, path: &str) -> std::io::Result<()> {
+        let temporary = format!("{}.tmp", path);
+        std::fs::write(&temporary, self.checkpoint())?;
+        std::fs::rename(&temporary, path)
+    }
+}
+
+impl CSP {
+    // Rebuilds the search saved in a checkpoint, the board and the pole counts are restored by
+    // assigning the saved assignment again. The limit counts the solutions found before as well.
+    pub fn resume_solutions(&mut self, text: &str, limit: Option<usize>) -> Result<Solutions<'_>, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(String::from("Not a checkpoint file"));
+        }
+        let mut clues = Vec::new();
+        let mut counters = Vec::new();
+        let mut found = 0;
+        let mut rng = None;
+        let mut shuffle_values = false;
+        let mut assignment = None;
+        let mut initial_domain = None;
+        let mut domains = None;
+        let mut explanations = Vec::new();
+        let mut jumps = 0;
+        let mut trail = Vec::new();
+        let mut stack = Vec::new();
+        for line in lines {
+            let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match keyword {
+                "puzzle" => {
+                    let sizes = parse_numbers::<usize>(rest)?;
+                    if sizes != [self.row_size, self.col_size, self.variables.len()] {
+                        return Err(String::from("The checkpoint was saved for a puzzle of another size"));
+                    }
+                }
+                "clues" => clues.push(parse_numbers::<i32>(rest)?),
+                "mode" => self.inference_mode = rest.parse()?,
+                "backjump" => {
+                    self.backjumping = match rest {
+                        "on" => true,
+                        "off" => false,
+                        _ => return Err(format!("Bad backjump setting '{}'", rest)),
+                    }
+                }
+                "found" => found = rest.parse().map_err(|_| format!("Bad solution count '{}'", rest))?,
+                "rng" if rest == "none" => rng = None,
+                "rng" => {
+                    let (state, usage) = rest.split_once(' ').unwrap_or((rest, "ties"));
+                    rng = Some(Rng::new(state.parse().map_err(|_| format!("Bad generator state '{}'", rest))?));
+                    shuffle_values = usage == "shuffle";
+                }
+                "counters" => counters.push(parse_numbers::<i32>(rest)?),
+                "assignment" => assignment = Some(parse_values(rest)?),
+                "root" => initial_domain = Some(parse_domain(rest)?),
+                "domains" => domains = Some(parse_domain(rest)?),
+                "explanations" => explanations = rest.split(' ').map(|set| self.parse_set(set)).collect::<Result<_, _>>()?,
+                "trail" => trail = self.parse_trail(rest)?,
+                "frame" => stack.push(self.parse_frame(rest)?),
+                "jump" => {
+                    self.parse_jump(rest, stack.last_mut().ok_or("The checkpoint has a jump line before any frame")?)?;
+                    jumps += 1;
+                }
+                _ => return Err(format!("Unknown checkpoint line '{}'", line)),
+            }
+        }
+
+        let puzzle_clues = [&self.row_pos_poles, &self.row_neg_poles, &self.col_pos_poles, &self.col_neg_poles];
+        if clues.len() != 4 || clues.iter().zip(puzzle_clues).any(|(saved, clues)| saved != clues) {
+            return Err(String::from("The checkpoint was saved for a puzzle with other clues"));
+        }
+        if self.backjumping && jumps != stack.len() {
+            return Err(String::from("The checkpoint backjumps but a frame has no jump line"));
+        }
+        if self.backjumping && !stack.is_empty() && explanations.len() != self.variables.len() {
+            return Err(String::from("The checkpoint backjumps but has no explanation for every magnet"));
+        }
+        let domains = match domains {
+            Some(domains) => domains,
+            None if stack.is_empty() => Vec::new(),
+            None => return Err(String::from("The checkpoint has frames but no domains")),
+        };
+        if !stack.is_empty() {
+            self.check_trail(&domains, &explanations, &trail, &stack)?;
+        }
+        let assignment = assignment.ok_or("The checkpoint has no assignment")?;
+        if assignment.len() != self.variables.len() {
+            return Err(String::from("The checkpoint assignment has the wrong number of magnets"));
+        }
+
+        self.reset();
+        let mut replayed = vec![Value::Unassigned; self.variables.len()];
+        for (var_index, value) in assignment.iter().enumerate() {
+            if *value != Value::Unassigned && !self.assign(*value, var_index, &mut replayed) {
+                return Err(format!("Magnet {} of the checkpoint overlaps another one", var_index));
+            }
+        }
+        if counters != self.current_counters() {
+            return Err(String::from("The pole counts of the checkpoint don't match its assignment"));
+        }
+
+        let solver = Solver {
+            csp: self,
+            assignment,
+            stack,
+            initial_domain,
+            domains,
+            explanations,
+            trail,
+            rng,
+            shuffle_values,
+            nodes: 0,
+            backtracks: 0,
+            limits: SolveLimits::default(),
+        };
+        Ok(Solutions {
+            solver,
+            limit,
+            found,
+        })
+    }
+
+    pub fn current_counters(&self) -> [Vec<i32>; 4] {
+        let counts = |clues: Vec<Clue>| clues.into_iter().map(|clue| self.current_count(clue)).collect();
+        [
+            counts((0..self.row_size).map(Clue::RowPositive).collect()),
+            counts((0..self.row_size).map(Clue::RowNegative).collect()),
+            counts((0..self.col_size).map(Clue::ColPositive).collect()),
+            counts((0..self.col_size).map(Clue::ColNegative).collect()),
+        ]
+    }
+
+    fn parse_frame(&self, text: &str) -> Result<Frame, String> {
+        let parts: Vec<&str> = text.splitn(5, ' ').collect();
+        if parts.len() != 5 {
+            return Err(format!("Bad frame '{}'", text));
+        }
+        let var_index: usize = parts[0].parse().map_err(|_| format!("Bad variable in frame '{}'", text))?;
+        let next_value: usize = parts[1].parse().map_err(|_| format!("Bad position in frame '{}'", text))?;
+        let assigned = match parts[2] {
+            "-" => None,
+            value => Some(*parse_values(value)?.first().ok_or_else(|| format!("Bad frame '{}'", text))?),
+        };
+        let values = parse_values(parts[3])?;
+        let trail_mark: usize = parts[4].parse().map_err(|_| format!("Bad trail mark in frame '{}'", text))?;
+        if var_index >= self.variables.len() || next_value > values.len() {
+            return Err(format!("Frame '{}' doesn't fit the puzzle", text));
+        }
+        Ok(Frame {
+            var_index,
+            values,
+            next_value,
+            trail_mark,
+            assigned,
+            conflict_set: Vec::new(),
+            solution_below: false,
+        })
+    }
+
+    fn parse_jump(&self, text: &str, frame: &mut Frame) -> Result<(), String> {
+        let mut parts = text.split(' ');
+        frame.solution_below = match parts.next() {
+            Some("0") => false,
+            Some("1") => true,
+            _ => return Err(format!("Bad jump '{}'", text)),
+        };
+        let conflict_set = parts.next().ok_or_else(|| format!("Bad jump '{}'", text))?;
+        frame.conflict_set = self.parse_set(conflict_set)?;
+        if parts.next().is_some() {
+            return Err(format!("Bad jump '{}'", text));
+        }
+        Ok(())
+    }
+
+    fn parse_trail(&self, text: &str) -> Result<Trail, String> {
+        if text == "_" {
+            return Ok(Vec::new());
+        }
+        text.split(' ')
+            .map(|entry| {
+                let bad = || format!("Bad trail entry '{}'", entry);
+                if let Some(var_index) = entry.strip_prefix('b') {
+                    return match var_index.parse::<usize>() {
+                        Ok(var_index) if var_index < self.variables.len() => Ok(TrailEntry::Blame(var_index)),
+                        _ => Err(bad()),
+                    };
+                }
+                let value = *parse_values(entry.get(..1).ok_or_else(bad)?)?.first().ok_or_else(bad)?;
+                let (var_index, position) = entry[1..].split_once('@').ok_or_else(bad)?;
+                let var_index: usize = var_index.parse().map_err(|_| bad())?;
+                let position: usize = position.parse().map_err(|_| bad())?;
+                if var_index >= self.variables.len() {
+                    return Err(bad());
+                }
+                Ok(TrailEntry::Removal(Removal { var_index, value, position }))
+            })
+            .collect()
+    }
+
+    // The domains must have one entry per variable, the trail marks must grow with the depth and
+    // undoing the whole trail must put every value back at a position its domain has and only
+    // drop culprits the explanations have
+    fn check_trail(&self, domains: &Domain, explanations: &Explanations, trail: &Trail, stack: &[Frame]) -> Result<(), String> {
+        if domains.len() != self.variables.len() {
+            return Err(String::from("The checkpoint domains don't fit the puzzle"));
+        }
+        let mut mark = 0;
+        for frame in stack {
+            if frame.trail_mark < mark || frame.trail_mark > trail.len() {
+                return Err(format!("Bad trail mark {} in checkpoint", frame.trail_mark));
+            }
+            mark = frame.trail_mark;
+        }
+        let mut restored = domains.clone();
+        let mut blamed: Vec<usize> = explanations.iter().map(|set| set.len()).collect();
+        for entry in trail.iter().rev() {
+            match entry {
+                TrailEntry::Removal(removal) => {
+                    if removal.position > restored[removal.var_index].len() {
+                        return Err(String::from("The checkpoint trail doesn't match its domains"));
+                    }
+                    CSP::restore_value(&mut restored, removal);
+                }
+                TrailEntry::Blame(var_index) => match blamed.get_mut(*var_index) {
+                    Some(count) if *count > 0 => *count -= 1,
+                    _ => return Err(String::from("The checkpoint trail doesn't match its explanations")),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_set(&self, text: &str) -> Result<Vec<VariableIndex>, String> {
+        if text == "_" {
+            return Ok(Vec::new());
+        }
+        text.split(',')
+            .map(|var_index| match var_index.parse::<usize>() {
+                Ok(var_index) if var_index < self.variables.len() => Ok(var_index),
+                _ => Err(format!("Bad variable '{}' in checkpoint", var_index)),
+            })
+            .collect()
+    }
+}
+
+fn join(numbers: &[i32]) -> String {
+    numbers.iter().map(|number| number.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn value_char(value: Value) -> char {
+    match value {
+        Value::Pole1PositivePole2Negative => 'p',
+        Value::Pole2PositivePole1Negative => 'n',
+        Value::Empty => 'e',
+        Value::Unassigned => 'u',
+    }
+}
+
+fn values_text(values: &[Value]) -> String {
+    if values.is_empty() {
+        return String::from("_");
+    }
+    values.iter().map(|value| value_char(*value)).collect()
+}
+
+fn set_text(set: &[VariableIndex]) -> String {
+    if set.is_empty() {
+        return String::from("_");
+    }
+    set.iter().map(|var_index| var_index.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn trail_text(trail: &Trail) -> String {
+    if trail.is_empty() {
+        return String::from("_");
+    }
+    trail
+        .iter()
+        .map(|entry| match entry {
+            TrailEntry::Removal(removal) => format!("{}{}@{}", value_char(removal.value), removal.var_index, removal.position),
+            TrailEntry::Blame(var_index) => format!("b{}", var_index),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn domain_text(domain: &Domain) -> String {
+    domain.iter().map(|values| values_text(values)).collect::<Vec<_>>().join(" ")
+}
+
+fn parse_values(text: &str) -> Result<Vec<Value>, String> {
+    if text == "_" {
+        return Ok(Vec::new());
+    }
+    text.chars()
+        .map(|letter| match letter {
+            'p' => Ok(Value::Pole1PositivePole2Negative),
+            'n' => Ok(Value::Pole2PositivePole1Negative),
+            'e' => Ok(Value::Empty),
+            'u' => Ok(Value::Unassigned),
+            _ => Err(format!("Unknown value '{}' in checkpoint", letter)),
+        })
+        .collect()
+}
+
+fn parse_domain(text: &str) -> Result<Domain, String> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    text.split(' ').map(parse_values).collect()
+}
+
+fn parse_numbers<T: std::str::FromStr>(text: &str) -> Result<Vec<T>, String> {
+    text.split_whitespace()
+        .map(|number| number.parse::<T>().map_err(|_| format!("Bad number '{}' in checkpoint", number)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::Progress;
+    use crate::test_puzzles::*;
+
+    fn puzzle_with(inference_mode: InferenceMode, backjumping: bool, seed: Option<u64>) -> CSP {
+        let mut csp = puzzle(COLUMN_CLUES_ONLY, inference_mode);
+        csp.backjumping = backjumping;
+        csp.seed = seed;
+        csp
+    }
+
+    // Enumerates the solutions, but stops once about `nodes` values were tried, saves a checkpoint
+    // and resumes it on a fresh copy of the puzzle for the rest
+    fn solutions_across_checkpoint(csp: &mut CSP, nodes: usize) -> Vec<Assignment> {
+        let mut found = Vec::new();
+        let mut solutions = csp.solutions(None);
+        while solutions.solver.nodes < nodes {
+            match solutions.advance(Some(nodes - solutions.solver.nodes)) {
+                Progress::Solution(solution) => found.push(solution),
+                Progress::Paused | Progress::Exhausted => break,
+                Progress::LimitReached(limit) => panic!("{}", limit),
+            }
+        }
+        let text = solutions.checkpoint();
+
+        let mut fresh = puzzle(COLUMN_CLUES_ONLY, InferenceMode::MAC);
+        let resumed = fresh.resume_solutions(&text, None).unwrap();
+        assert_eq!(resumed.found, found.len());
+        found.extend(resumed);
+        found
+    }
+
+    // The solutions found before and after the checkpoint are the ones of an uninterrupted run,
+    // in the same order
+    #[test]
+    fn resuming_neither_repeats_nor_skips_solutions() {
+        for inference_mode in [InferenceMode::None, InferenceMode::FC, InferenceMode::MAC] {
+            for backjumping in [false, true] {
+                let expected: Vec<Assignment> = puzzle_with(inference_mode, backjumping, None).solutions(None).collect();
+                for nodes in [0, 1500, 20000] {
+                    let found = solutions_across_checkpoint(&mut puzzle_with(inference_mode, backjumping, None), nodes);
+                    assert!(found == expected, "{:?}, backjumping {}, {} nodes", inference_mode, backjumping, nodes);
+                }
+            }
+        }
+    }
+
+    // The generator state is saved too, so a seeded search continues with the same decisions
+    #[test]
+    fn resuming_a_seeded_search_keeps_its_order() {
+        for (inference_mode, backjumping) in [(InferenceMode::FC, false), (InferenceMode::MAC, true)] {
+            let expected: Vec<Assignment> = puzzle_with(inference_mode, backjumping, Some(7)).solutions(None).collect();
+            for nodes in [1, 900, 20000] {
+                let found = solutions_across_checkpoint(&mut puzzle_with(inference_mode, backjumping, Some(7)), nodes);
+                assert!(found == expected, "{:?}, backjumping {}, {} nodes", inference_mode, backjumping, nodes);
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_a_checkpoint_of_another_puzzle() {
+        let mut csp = puzzle(COLUMN_CLUES_ONLY, InferenceMode::MAC);
+        let mut solutions = csp.solutions(None);
+        solutions.advance(Some(10));
+        let text = solutions.checkpoint();
+        assert!(puzzle(TEST_CASE_1, InferenceMode::MAC).resume_solutions(&text, None).is_err());
+    }
+}
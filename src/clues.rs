@@ -0,0 +1,160 @@
+use crate::csp::*;
+use crate::limits::{SolveLimits, SolveResult};
+use std::fmt;
+
+// A single row or column count from the puzzle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Clue {
+    RowPositive(usize),
+    RowNegative(usize),
+    ColPositive(usize),
+    ColNegative(usize),
+}
+
+impl fmt::Display for Clue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Clue::RowPositive(i) => write!(f, "row {} +", i),
+            Clue::RowNegative(i) => write!(f, "row {} -", i),
+            Clue::ColPositive(j) => write!(f, "column {} +", j),
+            Clue::ColNegative(j) => write!(f, "column {} -", j),
+        }
+    }
+}
+
+// Parses a clue written as r<row>+, r<row>-, c<col>+ or c<col>-, for example r3+
+impl std::str::FromStr for Clue {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Clue, String> {
+        let error = || format!("Clue '{}' must look like r3+, r3-, c2+ or c2-", text);
+        if text.len() < 3 || !text.is_ascii() {
+            return Err(error());
+        }
+        let index = text[1..text.len() - 1].parse::<usize>().map_err(|_| error())?;
+        match (&text[..1], &text[text.len() - 1..]) {
+            ("r", "+") => Ok(Clue::RowPositive(index)),
+            ("r", "-") => Ok(Clue::RowNegative(index)),
+            ("c", "+") => Ok(Clue::ColPositive(index)),
+            ("c", "-") => Ok(Clue::ColNegative(index)),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl CSP {
+    // All the clues of the puzzle that are known (not negative)
+    pub fn clues(&self) -> Vec<Clue> {
+        let mut clues = Vec::new();
+        clues.extend((0..self.row_size).map(Clue::RowPositive));
+        clues.extend((0..self.row_size).map(Clue::RowNegative));
+        clues.extend((0..self.col_size).map(Clue::ColPositive));
+        clues.extend((0..self.col_size).map(Clue::ColNegative));
+        clues.retain(|clue| self.clue_value(*clue) >= 0);
+        clues
+    }
+
+    // Whether the clue's line is on the board
+    pub fn has_clue(&self, clue: Clue) -> bool {
+        match clue {
+            Clue::RowPositive(i) | Clue::RowNegative(i) => i < self.row_size,
+            Clue::ColPositive(j) | Clue::ColNegative(j) => j < self.col_size,
+        }
+    }
+
+    pub fn clue_value(&self, clue: Clue) -> i32 {
+        match clue {
+            Clue::RowPositive(i) => self.row_pos_poles[i],
+            Clue::RowNegative(i) => self.row_neg_poles[i],
+            Clue::ColPositive(j) => self.col_pos_poles[j],
+            Clue::ColNegative(j) => self.col_neg_poles[j],
+        }
+    }
+
+    pub fn set_clue_value(&mut self, clue: Clue, value: i32) {
+        match clue {
+            Clue::RowPositive(i) => self.row_pos_poles[i] = value,
+            Clue::RowNegative(i) => self.row_neg_poles[i] = value,
+            Clue::ColPositive(j) => self.col_pos_poles[j] = value,
+            Clue::ColNegative(j) => self.col_neg_poles[j] = value,
+        }
+    }
+
+    // Number of poles of the clue's sign in the clue's line of the given board
+    pub fn clue_count(&self, clue: Clue, board: &[Vec<BoardCell>]) -> i32 {
+        let (cells, sign): (Vec<&BoardCell>, BoardCell) = match clue {
+            Clue::RowPositive(i) => (board[i].iter().collect(), BoardCell::Positive),
+            Clue::RowNegative(i) => (board[i].iter().collect(), BoardCell::Negative),
+            Clue::ColPositive(j) => (board.iter().map(|row| &row[j]).collect(), BoardCell::Positive),
+            Clue::ColNegative(j) => (board.iter().map(|row| &row[j]).collect(), BoardCell::Negative),
+        };
+        cells.into_iter().filter(|cell| **cell == sign).count() as i32
+    }
+
+    // Finds a minimal set of clues that already has no solution, returns None if the puzzle is
+    // solvable. Clues are dropped one at a time (made unknown) and stay dropped as long as the
+    // remaining clues still have no solution.
+    // Column clues are dropped first. The row clues are checked as soon as a row is filled, so the
+    // weakened puzzles that keep them are refuted much faster.
+    pub fn unsat_clue_core(&mut self) -> Option<Vec<Clue>> {
+        if self.is_satisfiable() {
+            return None;
+        }
+        let mut all_clues = self.clues();
+        all_clues.sort_by_key(|clue| matches!(clue, Clue::RowPositive(_) | Clue::RowNegative(_)));
+        let original: Vec<i32> = all_clues.iter().map(|clue| self.clue_value(*clue)).collect();
+
+        let mut core = Vec::new();
+        for (clue, value) in all_clues.iter().zip(&original) {
+            self.set_clue_value(*clue, -1);
+            if self.is_satisfiable() {
+                // needed for the contradiction
+                self.set_clue_value(*clue, *value);
+                core.push(*clue);
+            }
+        }
+
+        for (clue, value) in all_clues.iter().zip(&original) {
+            self.set_clue_value(*clue, *value);
+        }
+        Some(core)
+    }
+
+    // Re-solves the puzzle with its current clues, the board is left as it was. Backjumping is
+    // always on since a weakened puzzle often only contradicts itself in a few lines. A typo
+    // usually breaks the pole totals of every row or column though, and refuting that takes the
+    // search a huge tree, so past a node budget the row sweep counter decides instead.
+    pub fn is_satisfiable(&mut self) -> bool {
+        const SEARCH_NODES: usize = 20_000;
+        let (board, backjumping) = (self.board.clone(), self.backjumping);
+        self.backjumping = true;
+        let result = self.solve(&SolveLimits::default().with_max_nodes(SEARCH_NODES));
+        self.board = board;
+        self.backjumping = backjumping;
+        match result {
+            SolveResult::Solved(..) => true,
+            SolveResult::Unsat(_) => false,
+            SolveResult::LimitReached(..) => !self.count_models().is_zero(),
+        }
+    }
+
+    // Prints the clue lines of the board with only the clues of the core shown, in brackets
+    pub fn print_clue_core(&self, core: &[Clue]) {
+        let text = |clue: Clue| {
+            if core.contains(&clue) {
+                format!("[{}]", self.clue_value(clue))
+            } else {
+                String::from(".")
+            }
+        };
+        self.print_grid(&text, &|_, _| String::from("."));
+        println!();
+        println!("These {} clues can't be satisfied together:", core.len());
+        let clues = self.clues();
+        let mut core = core.to_vec();
+        core.sort_by_key(|clue| clues.iter().position(|c| c == clue));
+        for clue in core {
+            println!("    {} = {}", clue, self.clue_value(clue));
+        }
+    }
+}
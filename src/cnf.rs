@@ -0,0 +1,333 @@
+use crate::cdcl::{Cdcl, Lit};
+use crate::clues::Clue;
+use crate::csp::*;
+
+// A formula in conjunctive normal form over variables numbered from 1
+#[derive(Debug, Clone, Default)]
+pub struct Cnf {
+    pub num_vars: usize,
+    pub clauses: Vec<Vec<Lit>>,
+}
+
+// Outcome of the built-in SAT solver with its search statistics
+pub struct SatResult {
+    pub solution: Option<Assignment>,
+    pub decisions: usize,
+    pub conflicts: usize,
+    pub propagations: usize,
+    pub restarts: usize,
+}
+
+const VALUES: [Value; 3] = [Value::Pole1PositivePole2Negative, Value::Pole2PositivePole1Negative, Value::Empty];
+
+impl Cnf {
+    pub fn new_var(&mut self) -> Lit {
+        self.num_vars += 1;
+        self.num_vars as Lit
+    }
+
+    pub fn add(&mut self, clause: Vec<Lit>) {
+        self.clauses.push(clause);
+    }
+
+    // The sum of the literals is exactly count, with a sequential counter: register (i, j) is true
+    // exactly when at least j of the first i literals are true, so only registers up to count + 1
+    // are needed
+    pub fn exactly(&mut self, literals: &[Lit], count: usize) {
+        let n = literals.len();
+        if count > n {
+            self.add(Vec::new());
+            return;
+        }
+        let width = count + 1;
+        // registers[i][j - 1] for the first i + 1 literals, None where it can't be true
+        let mut registers: Vec<Vec<Option<Lit>>> = Vec::with_capacity(n);
+        for (i, literal) in literals.iter().copied().enumerate() {
+            let mut row = Vec::with_capacity(width);
+            for j in 1..=width {
+                if j > i + 1 {
+                    row.push(None);
+                    continue;
+                }
+                let register = self.new_var();
+                let previous = if i > 0 { registers[i - 1][j - 1] } else { None };
+                let previous_less = if j == 1 { None } else if i > 0 { registers[i - 1][j - 2] } else { None };
+                // register = previous or (literal and previous_less), previous_less is true for j = 1
+                if let Some(previous) = previous {
+                    self.add(vec![-previous, register]);
+                }
+                match (j, previous_less) {
+                    (1, _) => self.add(vec![-literal, register]),
+                    (_, Some(previous_less)) => self.add(vec![-literal, -previous_less, register]),
+                    _ => {}
+                }
+                let mut from_literal = vec![-register, literal];
+                from_literal.extend(previous);
+                self.add(from_literal);
+                if j > 1 {
+                    let mut from_less = vec![-register];
+                    from_less.extend(previous);
+                    from_less.extend(previous_less);
+                    self.add(from_less);
+                }
+                row.push(Some(register));
+            }
+            registers.push(row);
+        }
+        let last = |j: usize| if n == 0 || j == 0 { None } else { registers[n - 1][j - 1] };
+        if count > 0 {
+            match last(count) {
+                Some(register) => self.add(vec![register]),
+                None => self.add(Vec::new()),
+            }
+        }
+        if let Some(register) = last(count + 1) {
+            self.add(vec![-register]);
+        }
+    }
+
+    pub fn to_dimacs(&self, comments: &[String]) -> String {
+        let mut text = String::new();
+        for comment in comments {
+            text += &format!("c {}\n", comment);
+        }
+        text += &format!("p cnf {} {}\n", self.num_vars, self.clauses.len());
+        for clause in &self.clauses {
+            for literal in clause {
+                text += &format!("{} ", literal);
+            }
+            text += "0\n";
+        }
+        text
+    }
+
+    pub fn solver(&self) -> Cdcl {
+        let mut solver = Cdcl::new(self.num_vars);
+        for clause in &self.clauses {
+            solver.add_clause(clause);
+        }
+        solver
+    }
+}
+
+impl CSP {
+    // Variable of the value of the magnet, the first 3 * magnets variables are the magnets
+    pub fn magnet_literal(var_index: VariableIndex, value: Value) -> Lit {
+        let position = VALUES.iter().position(|candidate| *candidate == value).expect("No literal for an unassigned magnet");
+        (3 * var_index + position + 1) as Lit
+    }
+
+    // Literal true when the cell holds a pole of the sign
+    pub fn cell_literal(&self, cell: &Point, sign: &BoardCell) -> Lit {
+        let var_index = self.board_variable_association[cell.row][cell.col];
+        let first_pole = self.variables[var_index].poles[0] == *cell;
+        let value = match (first_pole, sign) {
+            (true, BoardCell::Positive) | (false, BoardCell::Negative) => Value::Pole1PositivePole2Negative,
+            _ => Value::Pole2PositivePole1Negative,
+        };
+        CSP::magnet_literal(var_index, value)
+    }
+
+    // Every magnet takes exactly one of its three values, touching cells of different magnets
+    // never hold like poles, and every known clue is an exact count over the cells of its line
+    pub fn encode_cnf(&self) -> Cnf {
+        let mut cnf = Cnf {
+            num_vars: 3 * self.variables.len(),
+            clauses: Vec::new(),
+        };
+        for var_index in 0..self.variables.len() {
+            let literals: Vec<Lit> = VALUES.iter().map(|value| CSP::magnet_literal(var_index, *value)).collect();
+            cnf.add(literals.clone());
+            for a in 0..literals.len() {
+                for b in a + 1..literals.len() {
+                    cnf.add(vec![-literals[a], -literals[b]]);
+                }
+            }
+        }
+        let signs = [BoardCell::Positive, BoardCell::Negative];
+        for row in 0..self.row_size {
+            for col in 0..self.col_size {
+                let cell = Point { row, col };
+                let right = Point { row, col: col + 1 };
+                let below = Point { row: row + 1, col };
+                for other in [right, below] {
+                    if other.row >= self.row_size || other.col >= self.col_size {
+                        continue;
+                    }
+                    if self.board_variable_association[row][col] == self.board_variable_association[other.row][other.col] {
+                        continue;
+                    }
+                    for sign in &signs {
+                        cnf.add(vec![-self.cell_literal(&cell, sign), -self.cell_literal(&other, sign)]);
+                    }
+                }
+            }
+        }
+        for clue in self.clues() {
+            let (cells, sign) = self.clue_cells(clue);
+            let literals: Vec<Lit> = cells.iter().map(|cell| self.cell_literal(cell, &sign)).collect();
+            cnf.exactly(&literals, self.clue_value(clue) as usize);
+        }
+        cnf
+    }
+
+    // Cells of the clue's line and the sign it counts
+    pub fn clue_cells(&self, clue: Clue) -> (Vec<Point>, BoardCell) {
+        let row = |i: usize| (0..self.col_size).map(|col| Point { row: i, col }).collect();
+        let col = |j: usize| (0..self.row_size).map(|row| Point { row, col: j }).collect();
+        match clue {
+            Clue::RowPositive(i) => (row(i), BoardCell::Positive),
+            Clue::RowNegative(i) => (row(i), BoardCell::Negative),
+            Clue::ColPositive(j) => (col(j), BoardCell::Positive),
+            Clue::ColNegative(j) => (col(j), BoardCell::Negative),
+        }
+    }
+
+    // Reads the magnets back from a model indexed by variable from 0
+    pub fn decode_model(&self, model: &[bool]) -> Result<Assignment, String> {
+        (0..self.variables.len())
+            .map(|var_index| {
+                let values: Vec<Value> = VALUES.iter().copied().filter(|value| model.get(CSP::magnet_literal(var_index, *value) as usize - 1) == Some(&true)).collect();
+                match values[..] {
+                    [value] => Ok(value),
+                    _ => Err(format!("The model gives magnet {} {} values instead of one", var_index, values.len())),
+                }
+            })
+            .collect()
+    }
+
+    pub fn solve_sat(&self) -> SatResult {
+        let mut solver = self.encode_cnf().solver();
+        let model = solver.solve();
+        SatResult {
+            solution: model.map(|model| self.decode_model(&model).expect("The encoding gives every magnet one value")),
+            decisions: solver.decisions,
+            conflicts: solver.conflicts,
+            restarts: solver.restarts,
+            propagations: solver.propagations,
+        }
+    }
+
+    // Counts the solutions with the SAT solver, blocking every solution found before solving again
+    pub fn count_sat(&self) -> usize {
+        let mut solver = self.encode_cnf().solver();
+        let mut count = 0;
+        while let Some(model) = solver.solve() {
+            count += 1;
+            let solution = self.decode_model(&model).expect("The encoding gives every magnet one value");
+            let blocking: Vec<Lit> = solution.iter().enumerate().map(|(var_index, value)| -CSP::magnet_literal(var_index, *value)).collect();
+            solver.add_clause(&blocking);
+        }
+        count
+    }
+
+    pub fn dimacs(&self) -> String {
+        let comments = vec![
+            format!("ravenous-polarity {}x{} board with {} magnets", self.row_size, self.col_size, self.variables.len()),
+            String::from("variable 3i+1: magnet i (from 0) has pole 1 positive, 3i+2: pole 2 positive, 3i+3: empty"),
+        ];
+        self.encode_cnf().to_dimacs(&comments)
+    }
+}
+
+// Reads a DIMACS CNF formula, comment lines are skipped
+pub fn parse_dimacs(text: &str) -> Result<Cnf, String> {
+    let mut cnf = Cnf::default();
+    let mut clause = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('c') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix("p cnf") {
+            let sizes: Vec<&str> = header.split_whitespace().collect();
+            cnf.num_vars = sizes.first().and_then(|size| size.parse().ok()).ok_or_else(|| format!("Bad header '{}'", line))?;
+            continue;
+        }
+        for literal in line.split_whitespace() {
+            let literal: Lit = literal.parse().map_err(|_| format!("Bad literal '{}' in the formula", literal))?;
+            if literal.unsigned_abs() as usize > cnf.num_vars {
+                return Err(format!("Variable {} is not declared in the header", literal.abs()));
+            }
+            if literal == 0 {
+                cnf.add(std::mem::take(&mut clause));
+            } else {
+                clause.push(literal);
+            }
+        }
+    }
+    if !clause.is_empty() {
+        return Err(String::from("The last clause of the formula has no terminating 0"));
+    }
+    Ok(cnf)
+}
+
+// Reads the output of a SAT solver: an optional s line, then the values as v lines or as bare
+// literals. Returns None when the solver found the formula unsatisfiable.
+pub fn parse_sat_model(text: &str, num_vars: usize) -> Result<Option<Vec<bool>>, String> {
+    let mut model = vec![false; num_vars];
+    for line in text.lines() {
+        let line = line.trim();
+        let upper = line.to_uppercase();
+        if upper.contains("UNSAT") {
+            return Ok(None);
+        }
+        if line.is_empty() || line.starts_with('c') || line.starts_with('s') || upper == "SAT" {
+            continue;
+        }
+        let literals = line.strip_prefix('v').unwrap_or(line);
+        for literal in literals.split_whitespace() {
+            let literal: Lit = literal.parse().map_err(|_| format!("Bad literal '{}' in the model", literal))?;
+            let var = literal.unsigned_abs() as usize;
+            if var > num_vars {
+                return Err(format!("Variable {} of the model is not in the formula", var));
+            }
+            if var > 0 {
+                model[var - 1] = literal > 0;
+            }
+        }
+    }
+    Ok(Some(model))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fixes the inputs of the constraint to every combination and checks that exactly the ones
+    // with `count` true inputs can be completed
+    #[test]
+    fn exactly_matches_brute_force() {
+        for n in 0..=5 {
+            for count in 0..=n + 1 {
+                let mut cnf = Cnf::default();
+                let literals: Vec<Lit> = (0..n).map(|_| cnf.new_var()).collect();
+                cnf.exactly(&literals, count);
+                for inputs in 0..1u32 << n {
+                    let mut solver = cnf.solver();
+                    for (i, literal) in literals.iter().enumerate() {
+                        solver.add_clause(&[if inputs >> i & 1 == 1 { *literal } else { -literal }]);
+                    }
+                    let expected = inputs.count_ones() as usize == count;
+                    assert_eq!(solver.solve().is_some(), expected, "n = {}, count = {}, inputs = {:b}", n, count, inputs);
+                }
+            }
+        }
+    }
+
+    // Negated literals count when they are true, that is when their variable is false
+    #[test]
+    fn exactly_counts_negated_literals() {
+        let mut cnf = Cnf::default();
+        let variables: Vec<Lit> = (0..4).map(|_| cnf.new_var()).collect();
+        let literals: Vec<Lit> = variables.iter().map(|variable| -variable).collect();
+        cnf.exactly(&literals, 1);
+        for inputs in 0..1u32 << 4 {
+            let mut solver = cnf.solver();
+            for (i, variable) in variables.iter().enumerate() {
+                solver.add_clause(&[if inputs >> i & 1 == 1 { *variable } else { -variable }]);
+            }
+            assert_eq!(solver.solve().is_some(), inputs.count_ones() == 3, "inputs = {:04b}", inputs);
+        }
+    }
+}
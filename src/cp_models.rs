@@ -0,0 +1,214 @@
+use crate::csp::*;
+
+// MiniZinc model shared by every puzzle, the puzzle itself goes in the data file. A magnet is
+// 1 (pole 1 positive), 2 (pole 2 positive) or 3 (empty), a cell 1 (+), -1 (-) or 0 (empty), and
+// unknown clues are -1.
+const MINIZINC_MODEL: &str = r#"% ravenous-polarity magnets puzzle
+include "globals.mzn";
+
+int: rows;
+int: cols;
+int: magnets;
+array[1..magnets] of 1..rows: pole1_row;
+array[1..magnets] of 1..cols: pole1_col;
+array[1..magnets] of 1..rows: pole2_row;
+array[1..magnets] of 1..cols: pole2_col;
+array[1..rows] of -1..cols: row_pos;
+array[1..rows] of -1..cols: row_neg;
+array[1..cols] of -1..rows: col_pos;
+array[1..cols] of -1..rows: col_neg;
+
+% 1: pole 1 positive, 2: pole 2 positive, 3: empty
+array[1..magnets] of var 1..3: magnet;
+% 1: positive, -1: negative, 0: empty
+array[1..rows, 1..cols] of var -1..1: cell;
+
+% the value of a magnet fixes the signs of its two cells
+constraint forall(m in 1..magnets)(
+    table([magnet[m], cell[pole1_row[m], pole1_col[m]], cell[pole2_row[m], pole2_col[m]]],
+          [| 1, 1, -1 | 2, -1, 1 | 3, 0, 0 |]));
+
+% like poles never touch, the two cells of a magnet always differ or are both empty
+constraint forall(r in 1..rows, c in 1..cols - 1)(cell[r, c] == 0 \/ cell[r, c] != cell[r, c + 1]);
+constraint forall(r in 1..rows - 1, c in 1..cols)(cell[r, c] == 0 \/ cell[r, c] != cell[r + 1, c]);
+
+% the clues
+constraint forall(r in 1..rows where row_pos[r] >= 0)(count([cell[r, c] | c in 1..cols], 1, row_pos[r]));
+constraint forall(r in 1..rows where row_neg[r] >= 0)(count([cell[r, c] | c in 1..cols], -1, row_neg[r]));
+constraint forall(c in 1..cols where col_pos[c] >= 0)(count([cell[r, c] | r in 1..rows], 1, col_pos[c]));
+constraint forall(c in 1..cols where col_neg[c] >= 0)(count([cell[r, c] | r in 1..rows], -1, col_neg[c]));
+
+solve satisfy;
+
+output ["magnet = \(magnet);\n"];
+"#;
+
+const MAGNET_VALUES: [Value; 3] = [Value::Pole1PositivePole2Negative, Value::Pole2PositivePole1Negative, Value::Empty];
+
+impl CSP {
+    pub fn minizinc_model(&self) -> String {
+        String::from(MINIZINC_MODEL)
+    }
+
+    // Data file of the puzzle for the MiniZinc model, rows and columns from 1
+    pub fn minizinc_data(&self) -> String {
+        let list = |numbers: Vec<String>| format!("[{}]", numbers.join(", "));
+        let poles = |pole: usize, row: bool| {
+            list(self.variables.iter().map(|variable| {
+                let point = &variable.poles[pole];
+                (if row { point.row } else { point.col } + 1).to_string()
+            }).collect())
+        };
+        let clues = |clues: &[i32]| list(clues.iter().map(|clue| clue.to_string()).collect());
+        let mut text = format!("% ravenous-polarity {}x{} board with {} magnets\n", self.row_size, self.col_size, self.variables.len());
+        text += &format!("rows = {};\ncols = {};\nmagnets = {};\n", self.row_size, self.col_size, self.variables.len());
+        text += &format!("pole1_row = {};\npole1_col = {};\n", poles(0, true), poles(0, false));
+        text += &format!("pole2_row = {};\npole2_col = {};\n", poles(1, true), poles(1, false));
+        text += &format!("row_pos = {};\nrow_neg = {};\n", clues(&self.row_pos_poles), clues(&self.row_neg_poles));
+        text += &format!("col_pos = {};\ncol_neg = {};\n", clues(&self.col_pos_poles), clues(&self.col_neg_poles));
+        text
+    }
+
+    // XCSP3 instance: magnet m[i] is 0 (pole 1 positive), 1 (pole 2 positive) or 2 (empty), cell
+    // s[r][c] is 1, -1 or 0. A table links every magnet to its cells, a table of conflicts keeps
+    // like poles apart and count constraints over the rows and columns of s express the clues.
+    pub fn xcsp3(&self) -> String {
+        let mut text = String::from("<instance format=\"XCSP3\" type=\"CSP\">\n");
+        text += &format!("  <!-- ravenous-polarity {}x{} board with {} magnets -->\n", self.row_size, self.col_size, self.variables.len());
+        text += "  <variables>\n";
+        text += &format!("    <array id=\"m\" note=\"0: pole 1 positive, 1: pole 2 positive, 2: empty\" size=\"[{}]\"> 0..2 </array>\n", self.variables.len());
+        text += &format!("    <array id=\"s\" note=\"1: positive, -1: negative, 0: empty\" size=\"[{}][{}]\"> -1..1 </array>\n", self.row_size, self.col_size);
+        text += "  </variables>\n  <constraints>\n";
+
+        text += "    <group>\n      <extension>\n        <list> %0 %1 %2 </list>\n        <supports> (0,1,-1)(1,-1,1)(2,0,0) </supports>\n      </extension>\n";
+        for variable in &self.variables {
+            let (pole1, pole2) = (&variable.poles[0], &variable.poles[1]);
+            text += &format!("      <args> m[{}] s[{}][{}] s[{}][{}] </args>\n", variable.index, pole1.row, pole1.col, pole2.row, pole2.col);
+        }
+        text += "    </group>\n";
+
+        text += "    <group>\n      <extension>\n        <list> %0 %1 </list>\n        <conflicts> (1,1)(-1,-1) </conflicts>\n      </extension>\n";
+        for row in 0..self.row_size {
+            for col in 0..self.col_size {
+                for (other_row, other_col) in [(row, col + 1), (row + 1, col)] {
+                    if other_row >= self.row_size || other_col >= self.col_size {
+                        continue;
+                    }
+                    if self.board_variable_association[row][col] == self.board_variable_association[other_row][other_col] {
+                        continue;
+                    }
+                    text += &format!("      <args> s[{}][{}] s[{}][{}] </args>\n", row, col, other_row, other_col);
+                }
+            }
+        }
+        text += "    </group>\n";
+
+        let lines = [
+            (&self.row_pos_poles, "1", true),
+            (&self.row_neg_poles, "-1", true),
+            (&self.col_pos_poles, "1", false),
+            (&self.col_neg_poles, "-1", false),
+        ];
+        for (clues, sign, row) in lines {
+            for (index, clue) in clues.iter().enumerate().filter(|(_, clue)| **clue >= 0) {
+                let list = if row { format!("s[{}][]", index) } else { format!("s[][{}]", index) };
+                text += &format!("    <count>\n      <list> {} </list>\n      <values> {} </values>\n      <condition> (eq,{}) </condition>\n    </count>\n", list, sign, clue);
+            }
+        }
+        text += "  </constraints>\n</instance>\n";
+        text
+    }
+
+    // Reads the magnet array from MiniZinc's output, either the model's own output or JSON.
+    // Returns None when MiniZinc reports the model unsatisfiable.
+    pub fn decode_minizinc_solution(&self, text: &str) -> Result<Option<Assignment>, String> {
+        if text.contains("=====UNSATISFIABLE=====") {
+            return Ok(None);
+        }
+        let start = text.find("magnet").ok_or("The output has no magnet array")?;
+        let open = start + text[start..].find('[').ok_or("The output has no magnet array")?;
+        let close = open + text[open..].find(']').ok_or("The magnet array of the output is not closed")?;
+        let values: Vec<usize> = text[open + 1..close]
+            .split(',')
+            .map(|value| value.trim().parse::<usize>().map_err(|_| format!("Bad magnet value '{}' in the output", value.trim())))
+            .collect::<Result<_, _>>()?;
+        self.assignment_from_indices(&values, 1).map(Some)
+    }
+
+    // Reads an XCSP3 instantiation, with or without the v prefixes of the competition output.
+    // The list may name the magnets one by one or as m[], other variables are skipped, and values
+    // may be compressed as <value>x<repeat>. Returns None when the solver reports unsatisfiable.
+    pub fn decode_xcsp3_solution(&self, text: &str) -> Result<Option<Assignment>, String> {
+        if text.lines().any(|line| line.trim() == "s UNSATISFIABLE") {
+            return Ok(None);
+        }
+        let text: String = text
+            .lines()
+            .map(|line| line.trim().strip_prefix("v ").unwrap_or(line.trim()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let list = element(&text, "list").ok_or("The solution has no list of variables")?;
+        let values = element(&text, "values").ok_or("The solution has no values")?;
+
+        let mut names = Vec::new();
+        for name in list.split_whitespace() {
+            match name {
+                "m[]" => names.extend((0..self.variables.len()).map(|index| format!("m[{}]", index))),
+                "s[][]" => names.extend((0..self.row_size * self.col_size).map(|_| String::new())),
+                _ => names.push(String::from(name)),
+            }
+        }
+        let mut expanded = Vec::new();
+        for value in values.split_whitespace() {
+            match value.split_once('x') {
+                Some((value, repeat)) => {
+                    let repeat: usize = repeat.parse().map_err(|_| format!("Bad repeat count in '{}x{}'", value, repeat))?;
+                    expanded.extend(std::iter::repeat_n(value, repeat));
+                }
+                None => expanded.push(value),
+            }
+        }
+        if expanded.len() != names.len() {
+            return Err(format!("The solution lists {} variables but {} values", names.len(), expanded.len()));
+        }
+
+        let mut magnets = vec![None; self.variables.len()];
+        for (name, value) in names.iter().zip(expanded) {
+            let index = match name.strip_prefix("m[").and_then(|rest| rest.strip_suffix(']')) {
+                Some(index) => index.parse::<usize>().ok().filter(|index| *index < self.variables.len()).ok_or_else(|| format!("Unknown magnet '{}'", name))?,
+                None => continue,
+            };
+            magnets[index] = Some(value.parse::<usize>().map_err(|_| format!("Bad value '{}' for {}", value, name))?);
+        }
+        let values: Vec<usize> = magnets
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| value.ok_or_else(|| format!("The solution has no value for m[{}]", index)))
+            .collect::<Result<_, _>>()?;
+        self.assignment_from_indices(&values, 0).map(Some)
+    }
+
+    // Magnet values numbered from first in the order pole 1 positive, pole 2 positive, empty
+    fn assignment_from_indices(&self, values: &[usize], first: usize) -> Result<Assignment, String> {
+        if values.len() != self.variables.len() {
+            return Err(format!("The solution has {} magnets instead of {}", values.len(), self.variables.len()));
+        }
+        values
+            .iter()
+            .map(|value| {
+                value
+                    .checked_sub(first)
+                    .and_then(|index| MAGNET_VALUES.get(index).copied())
+                    .ok_or_else(|| format!("Magnet value {} is out of range", value))
+            })
+            .collect()
+    }
+}
+
+// Text between <name ...> and </name>
+fn element<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let open = text.find(&format!("<{}", name))?;
+    let start = open + text[open..].find('>')? + 1;
+    let end = start + text[start..].find(&format!("</{}>", name))?;
+    Some(&text[start..end])
+}
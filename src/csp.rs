@@ -1,5 +1,16 @@
+use crate::backjumping::Explanations;
+use crate::clues::Clue;
+use crate::limits::{SearchBudget, SolveLimits, SolveResult};
+use crate::restarts::RestartPolicy;
+use crate::rng::Rng;
+use crate::solver::Step;
 use std::{collections::{HashSet, VecDeque}, option::Option};
 
+// Every clue and every cell of a printed board is right aligned in a column this wide
+pub const PRINT_WIDTH: usize = 4;
+
+#[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct CSP {
     pub row_size: usize,
     pub col_size: usize,
@@ -11,6 +22,11 @@ pub struct CSP {
     pub board_variable_association: Vec<Vec<usize>>,
     pub variables: Vec<Variable>,
     pub inference_mode: InferenceMode,
+    // when set, ties in variable and value ordering are broken randomly from this seed
+    pub seed: Option<u64>,
+    pub restart_policy: RestartPolicy,
+    // on a dead end, jump back to the deepest variable to blame instead of the previous one
+    pub backjumping: bool,
 
     curr_row_pos_poles: Vec<i32>,
     curr_row_neg_poles: Vec<i32>,
@@ -18,7 +34,7 @@ pub struct CSP {
     curr_col_neg_poles: Vec<i32>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Point {
     pub row: usize,
     pub col: usize,
@@ -34,6 +50,23 @@ pub type VariableIndex = usize;
 pub type PoleNumber = u8;
 pub type Domain = Vec<Vec<Value>>;
 
+// A value pruned from the domain of a variable by inference, with the position it had, so the
+// pruning can be undone and the domain gets back its exact order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Removal {
+    pub var_index: VariableIndex,
+    pub value: Value,
+    pub position: usize,
+}
+// Every change inference makes below a level of the search, undone in reverse order on backtrack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrailEntry {
+    Removal(Removal),
+    // a culprit added at the end of the explanation of the variable, with backjumping
+    Blame(VariableIndex),
+}
+pub type Trail = Vec<TrailEntry>;
+
 // A magnet slot can either be empty or be placed in one of the two directions
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Value {
@@ -65,14 +98,42 @@ pub struct ConstraintArc {
     pub constraint: Constraint,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum InferenceMode {
+    // plain backtracking, only is_consistent prunes the search
+    None,
     FC,
+    #[default]
     MAC,
 }
 
+impl std::str::FromStr for InferenceMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<InferenceMode, String> {
+        match name.to_lowercase().as_str() {
+            "none" | "bt" => Ok(InferenceMode::None),
+            "fc" => Ok(InferenceMode::FC),
+            "mac" | "ac3" => Ok(InferenceMode::MAC),
+            _ => Err(format!("Unknown inference mode '{}'", name)),
+        }
+    }
+}
+
+impl std::fmt::Display for InferenceMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            InferenceMode::None => write!(f, "none"),
+            InferenceMode::FC => write!(f, "fc"),
+            InferenceMode::MAC => write!(f, "mac"),
+        }
+    }
+}
+
 
 impl CSP {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         row_size: usize,
         col_size: usize,
@@ -149,84 +210,122 @@ impl CSP {
             board_variable_association,
             variables,
             inference_mode,
+            seed: None,
+            restart_policy: RestartPolicy::Never,
+            backjumping: false,
         }
     }
 
-    pub fn solve(&mut self) -> Option<Assignment> {
-        let mut initial_assignment: Assignment = vec![Value::Unassigned; self.variables.len()];
-        let initial_domain: Domain = vec![
+    // Searches for a solution within the limits, pass SolveLimits::default() to search without any.
+    // With a restart policy the search starts over from the root every time the policy's number
+    // of backtracks is used up, the random tie-breaking then leads it into another tree.
+    // The board is left filled in with the solution.
+    pub fn solve(&mut self, limits: &SolveLimits) -> SolveResult {
+        let mut budget = SearchBudget::new(limits);
+        let restart_policy = self.restart_policy;
+        let mut solver = self.solver();
+        let mut restarts = 0;
+        let mut backtracks_at_restart = 0;
+        let solution = loop {
+            let step = solver.step();
+            let within_limits = budget.check(solver.nodes, solver.backtracks, restarts);
+            match step {
+                Step::Solution(solution) => break Some(solution),
+                Step::Exhausted => break None,
+                _ if !within_limits => break None,
+                _ => {}
+            }
+            if let Some(threshold) = restart_policy.threshold(restarts) {
+                if solver.backtracks - backtracks_at_restart >= threshold {
+                    let root = solver.csp.initial_domain();
+                    solver.restart(root);
+                    restarts += 1;
+                    backtracks_at_restart = solver.backtracks;
+                }
+            }
+        };
+        budget.finish(solution)
+    }
+
+    // Every magnet slot starts with all three values available
+    pub fn initial_domain(&self) -> Domain {
+        vec![
             vec![
                 Value::Pole1PositivePole2Negative,
                 Value::Pole2PositivePole1Negative,
                 Value::Empty
             ];
             self.variables.len()
-        ];
-        self.backtrack(initial_domain, &mut initial_assignment)
+        ]
     }
 
-    fn backtrack(
-        &mut self,
-        domains: Domain,
-        assignment: &mut Assignment,
-    ) -> Option<Assignment> {
-
-        if self.is_complete(&assignment) {
-            return Some(assignment.clone());
-        }
-
-        if let Some(var_index) = self.select_unassigned_variable(&domains, &assignment) {
-            for value in self.order_domain_values(var_index, &domains, assignment) {
-                if self.assign(value, var_index, assignment) {
-                    if self.is_consistent(var_index) {
-                        let (feasible, inferred_domains) =
-                            self.inference(var_index, &domains, &assignment);
-                        if feasible {
-                            if let Some(result) =
-                                self.backtrack(inferred_domains, assignment)
-                            {
-                                return Some(result);
-                            }
-                        }
-                    }
-                    self.unassign(value, var_index, assignment);
-                }
-            }
-        }
-        None
+    // Clears the board and the current pole counts left over from a previous search
+    pub fn reset(&mut self) {
+        self.board = vec![vec![BoardCell::Unassigned; self.col_size]; self.row_size];
+        self.curr_row_pos_poles = vec![0; self.row_size];
+        self.curr_row_neg_poles = vec![0; self.row_size];
+        self.curr_col_pos_poles = vec![0; self.col_size];
+        self.curr_col_neg_poles = vec![0; self.col_size];
     }
 
-    fn inference(
+    // Prunes the domains in place and records every removal on the trail. On a wipeout the
+    // domains are left half pruned, the caller undoes the trail.
+    pub fn inference(
         &self,
         var_index: usize,
-        domains: &Domain,
+        domains: &mut Domain,
+        trail: &mut Trail,
         assignment: &Assignment,
-    ) -> (bool, Domain) {
+    ) -> bool {
+
+        if self.inference_mode == InferenceMode::None {
+            return true;
+        }
 
         let mut arc_queue: VecDeque<ConstraintArc> = VecDeque::new();
 
         self.generate_arc_constraints(var_index, assignment, &mut arc_queue, var_index);
         if self.inference_mode == InferenceMode::FC {
-            self.forward_checking(domains, assignment, arc_queue)
-        } else if self.inference_mode == InferenceMode::MAC {
-            self.maintaining_arc_consistency(domains, assignment, arc_queue)
+            self.forward_checking(domains, trail, assignment, arc_queue)
         } else {
-            (false, domains.clone())
+            self.maintaining_arc_consistency(domains, trail, assignment, arc_queue)
         }
     }
 
-    pub fn remove_value_from_domain(value: Value, domain: &mut Vec<Value>) -> bool {
-        if domain.contains(&value) {
-            if let Some(pos) = domain.iter().position(|x| *x == value) {
-                domain.swap_remove(pos);
-                return true;
-            }
+    pub fn remove_value_from_domain(var_index: VariableIndex, value: Value, domains: &mut Domain, trail: &mut Trail) -> bool {
+        if let Some(position) = domains[var_index].iter().position(|x| *x == value) {
+            domains[var_index].swap_remove(position);
+            trail.push(TrailEntry::Removal(Removal { var_index, value, position }));
+            return true;
         }
         false
     }
 
+    // Puts back the values removed and drops the culprits blamed since the trail had `length`
+    // entries, latest first
+    pub fn undo_trail(domains: &mut Domain, explanations: &mut Explanations, trail: &mut Trail, length: usize) {
+        while trail.len() > length {
+            match trail.pop() {
+                Some(TrailEntry::Removal(removal)) => CSP::restore_value(domains, &removal),
+                Some(TrailEntry::Blame(var_index)) => {
+                    explanations[var_index].pop();
+                }
+                None => {}
+            }
+        }
+    }
+
+    // The inverse of the swap_remove in remove_value_from_domain
+    pub fn restore_value(domains: &mut Domain, removal: &Removal) {
+        let domain = &mut domains[removal.var_index];
+        domain.push(removal.value);
+        let last = domain.len() - 1;
+        domain.swap(removal.position, last);
+    }
+
     // Given the value of xi, this function retuns the value that xj cant be based on the sign of
     // the poles and their possitions
+    #[allow(clippy::if_same_then_else)]
     pub fn get_neighbor_pole_based_inconsistent_value(xi_value: Value, xi_pole_index: PoleNumber, xj_pole_index: PoleNumber) -> Option<Value> {
         match xi_value {
             Value::Pole1PositivePole2Negative => {
@@ -259,7 +358,7 @@ impl CSP {
         }
     }
 
-    pub fn revise(&self, constraint_arc: &ConstraintArc, inferred_domains: &mut Domain, assignment: &Assignment) -> (bool, bool) {
+    pub fn revise(&self, constraint_arc: &ConstraintArc, inferred_domains: &mut Domain, trail: &mut Trail, assignment: &Assignment) -> (bool, bool) {
         let (xi_pole_index, xj_pole_index) = match constraint_arc.constraint {
             Constraint::SignBased(xi_pole_index, xj_pole_index) => {
                 (xi_pole_index, xj_pole_index)
@@ -306,11 +405,11 @@ impl CSP {
                 }
                 revised = !to_be_deleted.is_empty();
                 for value in to_be_deleted {
-                    CSP::remove_value_from_domain(value, &mut inferred_domains[xi_index]);
+                    CSP::remove_value_from_domain(xi_index, value, inferred_domains, trail);
                 }
         }
 
-        if inferred_domains[xi_index].len() == 0 {
+        if inferred_domains[xi_index].is_empty() {
             return (false, false)
         }
         (true, revised)
@@ -318,6 +417,7 @@ impl CSP {
 
     // Given the value of xi, this function retuns the value that xj cant be based on the limits of
     // positive and negatives signs in each row
+    #[allow(clippy::collapsible_match)]
     pub fn get_neighbor_limit_based_inconsistent_value(&self, xi_index: VariableIndex, xj_index: VariableIndex, xi_value: Value, xi_pole_index: PoleNumber, xj_pole_index: PoleNumber, assignment: &Assignment) -> Option<Value> {
 
         // self.print_board();
@@ -403,20 +503,16 @@ impl CSP {
                         unassigned_vars_in_row.insert(curr_var_index);
                     }
                 }
-                if unassigned_vars_in_row.len() == 0 {
+                if unassigned_vars_in_row.is_empty() {
                     match xi_value {
                         Value::Pole1PositivePole2Negative => {
-                            if xi_pole_index == 0  {
-                                Some(Value::Empty)
-                            } else if xi_pole_index == 0 {
+                            if xi_pole_index == 0 {
                                 Some(Value::Empty)
                             } else { None }
                         },
                         Value::Pole2PositivePole1Negative => {
                             if xi_pole_index == 1 {
                                 Some(Value::Empty)
-                            } else if xi_pole_index == 1 {
-                                Some(Value::Empty)
                             } else { None }
                         },
                         _ => { None }
@@ -431,20 +527,16 @@ impl CSP {
                         unassigned_vars_in_row.insert(curr_var_index);
                     }
                 }
-                if unassigned_vars_in_row.len() == 0 {
+                if unassigned_vars_in_row.is_empty() {
                     match xi_value {
                         Value::Pole1PositivePole2Negative => {
-                            if xi_pole_index == 1  {
-                                Some(Value::Empty)
-                            } else if xi_pole_index == 1 {
+                            if xi_pole_index == 1 {
                                 Some(Value::Empty)
                             } else { None }
                         },
                         Value::Pole2PositivePole1Negative => {
                             if xi_pole_index == 0 {
                                 Some(Value::Empty)
-                            } else if xi_pole_index == 0 {
-                                Some(Value::Empty)
                             } else { None }
                         },
                         _ => { None }
@@ -518,20 +610,16 @@ impl CSP {
                         unassigned_vars_in_col.insert(curr_var_index);
                     }
                 }
-                if unassigned_vars_in_col.len() == 0 {
+                if unassigned_vars_in_col.is_empty() {
                     match xi_value {
                         Value::Pole1PositivePole2Negative => {
-                            if xi_pole_index == 0  {
-                                Some(Value::Empty)
-                            } else if xi_pole_index == 0 {
+                            if xi_pole_index == 0 {
                                 Some(Value::Empty)
                             } else { None }
                         },
                         Value::Pole2PositivePole1Negative => {
                             if xi_pole_index == 1 {
                                 Some(Value::Empty)
-                            } else if xi_pole_index == 1 {
-                                Some(Value::Empty)
                             } else { None }
                         },
                         _ => { None }
@@ -545,20 +633,16 @@ impl CSP {
                         unassigned_vars_in_col.insert(curr_var_index);
                     }
                 }
-                if unassigned_vars_in_col.len() == 0 {
+                if unassigned_vars_in_col.is_empty() {
                     match xi_value {
                         Value::Pole1PositivePole2Negative => {
-                            if xi_pole_index == 1  {
-                                Some(Value::Empty)
-                            } else if xi_pole_index == 1 {
+                            if xi_pole_index == 1 {
                                 Some(Value::Empty)
                             } else { None }
                         },
                         Value::Pole2PositivePole1Negative => {
                             if xi_pole_index == 0 {
                                 Some(Value::Empty)
-                            } else if xi_pole_index == 0 {
-                                Some(Value::Empty)
                             } else { None }
                         },
                         _ => { None }
@@ -620,41 +704,57 @@ impl CSP {
     }
 
     pub fn print_board(&self) {
-        print!("{:8}", ' ');
-        for i in &self.col_pos_poles {
-            print!("{:4}", i);
-        }
-        println!();
-        print!("{:8}", ' ');
-        for i in &self.col_neg_poles {
-            print!("{:4}", i);
+        self.print_cells(&self.board);
+    }
+
+    pub fn print_cells(&self, board: &[Vec<BoardCell>]) {
+        let clue_text = |clue: Clue| self.clue_value(clue).to_string();
+        let cell_text = |i: usize, j: usize| match board[i][j] {
+            BoardCell::Positive => String::from("+"),
+            BoardCell::Negative => String::from("-"),
+            BoardCell::Empty => String::new(),
+            BoardCell::Unassigned => String::from("*"),
+        };
+        self.print_grid(&clue_text, &cell_text);
+    }
+
+    // Prints the two lines of column clues and then every row with its two clues followed by
+    // its cells, the text of each clue and cell is given by the closures
+    pub fn print_grid(&self, clue_text: &dyn Fn(Clue) -> String, cell_text: &dyn Fn(usize, usize) -> String) {
+        for clue in [Clue::ColPositive as fn(usize) -> Clue, Clue::ColNegative] {
+            print!("{:w$}", ' ', w = 2 * PRINT_WIDTH);
+            for j in 0..self.col_size {
+                print!("{:>w$}", clue_text(clue(j)), w = PRINT_WIDTH);
+            }
+            println!();
         }
-        println!();
         for i in 0..self.row_size {
-            print!("{:4}", self.row_pos_poles[i]);
-            print!("{:4}", self.row_neg_poles[i]);
-
-            for cell in &self.board[i] {
-                match cell {
-                    BoardCell::Positive => {
-                        print!("   {}", '+');
-                    }
-                    BoardCell::Negative => {
-                        print!("   {}", '-');
-                    }
-                    BoardCell::Empty => {
-                        print!("   {}", ' ');
-                    }
-                    BoardCell::Unassigned => {
-                        print!("   {}", '*');
-                    }
-                }
+            print!("{:>w$}", clue_text(Clue::RowPositive(i)), w = PRINT_WIDTH);
+            print!("{:>w$}", clue_text(Clue::RowNegative(i)), w = PRINT_WIDTH);
+            for j in 0..self.col_size {
+                print!("{:>w$}", cell_text(i, j), w = PRINT_WIDTH);
             }
             println!();
         }
     }
 
-    fn assign(&mut self, value: Value, var_index: usize, assignment: &mut Assignment) -> bool {
+    // Fills a board with the signs given by a (possibly partial) assignment
+    pub fn board_from_assignment(&self, assignment: &Assignment) -> Vec<Vec<BoardCell>> {
+        let mut board = vec![vec![BoardCell::Unassigned; self.col_size]; self.row_size];
+        for variable in &self.variables {
+            let (pole1, pole2) = match assignment[variable.index] {
+                Value::Pole1PositivePole2Negative => (BoardCell::Positive, BoardCell::Negative),
+                Value::Pole2PositivePole1Negative => (BoardCell::Negative, BoardCell::Positive),
+                Value::Empty => (BoardCell::Empty, BoardCell::Empty),
+                Value::Unassigned => continue,
+            };
+            board[variable.poles[0].row][variable.poles[0].col] = pole1;
+            board[variable.poles[1].row][variable.poles[1].col] = pole2;
+        }
+        board
+    }
+
+    pub fn assign(&mut self, value: Value, var_index: usize, assignment: &mut Assignment) -> bool {
         let v = &self.variables[var_index];
         match value {
             Value::Pole1PositivePole2Negative => {
@@ -695,7 +795,7 @@ impl CSP {
         true
     }
 
-    fn unassign(&mut self, value: Value, var_index: usize, assignment: &mut Assignment) {
+    pub fn unassign(&mut self, value: Value, var_index: usize, assignment: &mut Assignment) {
         let v = &self.variables[var_index];
         self.board[v.poles[0].row][v.poles[0].col] = BoardCell::Unassigned;
         self.board[v.poles[1].row][v.poles[1].col] = BoardCell::Unassigned;
@@ -717,36 +817,42 @@ impl CSP {
         assignment[var_index] = Value::Unassigned;
     }
 
-    // This function uses the MRV heuristic
-    fn select_unassigned_variable(
+    // MRV, ties go to the lowest index unless a generator is given, then to a random one among
+    // all the tied variables
+    pub fn select_unassigned_variable_with(
         &self,
         domains: &Domain,
         assignment: &Assignment,
+        rng: Option<&mut Rng>,
     ) -> Option<usize> {
-        let mut mrv_index = 0;
-        let mut mrv_value = std::usize::MAX;
+        let mut ties: Vec<VariableIndex> = Vec::new();
+        let mut mrv_value = usize::MAX;
         for i in 0..self.variables.len() {
             if assignment[i] == Value::Unassigned {
                 if domains[i].len() < mrv_value {
                     mrv_value = domains[i].len();
-                    mrv_index = i;
+                    ties.clear();
+                    ties.push(i);
+                } else if domains[i].len() == mrv_value {
+                    ties.push(i);
                 }
             }
         }
 
-        if assignment[mrv_index] == Value::Unassigned {
-            Some(mrv_index)
-        } else {
-            None
+        match rng {
+            Some(rng) if !ties.is_empty() => Some(ties[rng.below(ties.len())]),
+            _ => ties.first().copied(),
         }
     }
 
-    // LCV 
-    fn order_domain_values(
+    // LCV, values with the same score keep the domain order unless a generator is given, then
+    // they come in random order
+    pub fn order_domain_values_with(
         &self,
         var_index: usize,
         domains: &Domain,
-        assignment: &Assignment
+        assignment: &Assignment,
+        rng: Option<&mut Rng>,
     ) -> Vec<Value> {
         // Turn of LCV
         // return domains[var_index].clone();
@@ -756,7 +862,10 @@ impl CSP {
             constraint_score += self.calculate_constraint_score(*value, var_index, domains, assignment);
             ordered_domain_values.push((*value, constraint_score));
         }
-        ordered_domain_values.sort_by(|a, b| a.1.cmp(&b.1));
+        if let Some(rng) = rng {
+            rng.shuffle(&mut ordered_domain_values);
+        }
+        ordered_domain_values.sort_by_key(|a| a.1);
         ordered_domain_values
             .iter()
             .map(|v| v.0)
@@ -827,7 +936,7 @@ impl CSP {
                         constraint_score += 1;
                     }
                     // If this value results in a 0 domain, then increase the constraint more
-                    if to_be_deleted.len() != 0 && domains[xi_index].len() == 1 {
+                    if !to_be_deleted.is_empty() && domains[xi_index].len() == 1 {
                         constraint_score += 5;
                     }
                 }
@@ -847,7 +956,7 @@ impl CSP {
                 col: cell.col,
             });
         }
-        if cell.row as i64 - 1 >= 0
+        if cell.row >= 1
         && cell.row as i64 - 1 != same_variable_cell.row as i64
         && cell.col != same_variable_cell.col {
             neighboring_cells.push(Point {
@@ -863,7 +972,7 @@ impl CSP {
                 col: cell.col + 1,
             });
         }
-        if cell.col as i64 - 1 >= 0
+        if cell.col >= 1
         && cell.row != same_variable_cell.row
         && cell.col as i64 - 1 != same_variable_cell.col as i64 {
             neighboring_cells.push(Point {
@@ -899,7 +1008,7 @@ impl CSP {
         neighboring_cells
     }
 
-    fn is_complete(&self, assignment: &Assignment) -> bool {
+    pub fn is_complete(&self, assignment: &Assignment) -> bool {
         if !assignment
             .iter()
             .fold(true, |acc, v| acc & (*v != Value::Unassigned))
@@ -910,7 +1019,27 @@ impl CSP {
         true
     }
 
-    fn check_neighbors_pole_sign_constraint(&self, cell: &Point) -> bool {
+    // Number of poles of the clue's sign currently on the board in the clue's line
+    pub fn current_count(&self, clue: Clue) -> i32 {
+        match clue {
+            Clue::RowPositive(i) => self.curr_row_pos_poles[i],
+            Clue::RowNegative(i) => self.curr_row_neg_poles[i],
+            Clue::ColPositive(j) => self.curr_col_pos_poles[j],
+            Clue::ColNegative(j) => self.curr_col_neg_poles[j],
+        }
+    }
+
+    // A negative clue means the number of poles is unknown, so it never constrains the line
+    pub fn exceeds_clue(count: i32, clue: i32) -> bool {
+        clue >= 0 && count > clue
+    }
+
+    pub fn misses_clue(count: i32, clue: i32) -> bool {
+        clue >= 0 && count != clue
+    }
+
+    #[allow(clippy::collapsible_if)]
+    pub fn check_neighbors_pole_sign_constraint(&self, cell: &Point) -> bool {
         let value = &self.board[cell.row][cell.col];
         match value {
             BoardCell::Positive => {
@@ -919,7 +1048,7 @@ impl CSP {
                         return false;
                     }
                 }
-                if cell.row as i64 - 1 >= 0 {
+                if cell.row >= 1 {
                     if self.board[cell.row - 1][cell.col] == BoardCell::Positive {
                         return false;
                     }
@@ -929,7 +1058,7 @@ impl CSP {
                         return false;
                     }
                 }
-                if cell.col as i64 - 1 >= 0 {
+                if cell.col >= 1 {
                     if self.board[cell.row][cell.col - 1] == BoardCell::Positive {
                         return false;
                     }
@@ -941,7 +1070,7 @@ impl CSP {
                         return false;
                     }
                 }
-                if cell.row as i64 - 1 >= 0 {
+                if cell.row >= 1 {
                     if self.board[cell.row - 1][cell.col] == BoardCell::Negative {
                         return false;
                     }
@@ -951,7 +1080,7 @@ impl CSP {
                         return false;
                     }
                 }
-                if cell.col as i64 - 1 >= 0 {
+                if cell.col >= 1 {
                     if self.board[cell.row][cell.col - 1] == BoardCell::Negative {
                         return false;
                     }
@@ -962,7 +1091,8 @@ impl CSP {
         true
     }
 
-    fn is_consistent(&self, var_index: VariableIndex) -> bool {
+    #[allow(clippy::collapsible_if)]
+    pub fn is_consistent(&self, var_index: VariableIndex) -> bool {
         let var = &self.variables[var_index];
         // pole sign based cinssitency
         for pole in &var.poles {
@@ -983,16 +1113,16 @@ impl CSP {
                 poles_row_all_assigned &= self.board[poles_row][j] != BoardCell::Unassigned;
             }
             if poles_row_all_assigned {
-                if self.curr_row_pos_poles[poles_row] != self.row_pos_poles[poles_row]
-                || self.curr_row_neg_poles[poles_row] != self.row_neg_poles[poles_row] {
+                if CSP::misses_clue(self.curr_row_pos_poles[poles_row], self.row_pos_poles[poles_row])
+                || CSP::misses_clue(self.curr_row_neg_poles[poles_row], self.row_neg_poles[poles_row]) {
                     return false
                 }
             }
 
             // if there are some unassigned cells left then the curr limit has to be lower than the
             // total limit for that row
-            if self.curr_row_pos_poles[poles_row] > self.row_pos_poles[poles_row]
-                || self.curr_row_neg_poles[poles_row] > self.row_neg_poles[poles_row] {
+            if CSP::exceeds_clue(self.curr_row_pos_poles[poles_row], self.row_pos_poles[poles_row])
+                || CSP::exceeds_clue(self.curr_row_neg_poles[poles_row], self.row_neg_poles[poles_row]) {
                 return false
             }
 
@@ -1004,8 +1134,8 @@ impl CSP {
                 pole1_col_all_assigned &= self.board[i][pole1_col] != BoardCell::Unassigned;
             }
             if pole1_col_all_assigned {
-                if self.curr_col_pos_poles[pole1_col] != self.col_pos_poles[pole1_col]
-                || self.curr_col_neg_poles[pole1_col] != self.col_neg_poles[pole1_col] {
+                if CSP::misses_clue(self.curr_col_pos_poles[pole1_col], self.col_pos_poles[pole1_col])
+                || CSP::misses_clue(self.curr_col_neg_poles[pole1_col], self.col_neg_poles[pole1_col]) {
                     return false
                 }
             }
@@ -1014,18 +1144,18 @@ impl CSP {
                 pole2_col_all_assigned &= self.board[i][pole2_col] != BoardCell::Unassigned;
             }
             if pole2_col_all_assigned {
-                if self.curr_col_pos_poles[pole2_col] != self.col_pos_poles[pole2_col]
-                || self.curr_col_neg_poles[pole2_col] != self.col_neg_poles[pole2_col] {
+                if CSP::misses_clue(self.curr_col_pos_poles[pole2_col], self.col_pos_poles[pole2_col])
+                || CSP::misses_clue(self.curr_col_neg_poles[pole2_col], self.col_neg_poles[pole2_col]) {
                     return false
                 }
             }
 
-            if self.curr_col_pos_poles[pole1_col] > self.col_pos_poles[pole1_col]
-                || self.curr_col_neg_poles[pole1_col] > self.col_neg_poles[pole1_col] {
+            if CSP::exceeds_clue(self.curr_col_pos_poles[pole1_col], self.col_pos_poles[pole1_col])
+                || CSP::exceeds_clue(self.curr_col_neg_poles[pole1_col], self.col_neg_poles[pole1_col]) {
                 return false
             }
-            if self.curr_col_pos_poles[pole2_col] > self.col_pos_poles[pole2_col]
-                || self.curr_col_neg_poles[pole2_col] > self.col_neg_poles[pole2_col] {
+            if CSP::exceeds_clue(self.curr_col_pos_poles[pole2_col], self.col_pos_poles[pole2_col])
+                || CSP::exceeds_clue(self.curr_col_neg_poles[pole2_col], self.col_neg_poles[pole2_col]) {
                 return false
             }
         // if this is a vertical magnet
@@ -1037,8 +1167,8 @@ impl CSP {
                 pole1_row_all_assigned &= self.board[pole1_row][j] != BoardCell::Unassigned;
             }
             if pole1_row_all_assigned {
-                if self.curr_row_pos_poles[pole1_row] != self.row_pos_poles[pole1_row]
-                || self.curr_row_neg_poles[pole1_row] != self.row_neg_poles[pole1_row] {
+                if CSP::misses_clue(self.curr_row_pos_poles[pole1_row], self.row_pos_poles[pole1_row])
+                || CSP::misses_clue(self.curr_row_neg_poles[pole1_row], self.row_neg_poles[pole1_row]) {
                     return false
                 }
             }
@@ -1047,32 +1177,32 @@ impl CSP {
                 pole2_row_all_assigned &= self.board[pole2_row][j] != BoardCell::Unassigned;
             }
             if pole2_row_all_assigned {
-                if self.curr_row_pos_poles[pole2_row] != self.row_pos_poles[pole2_row]
-                || self.curr_row_neg_poles[pole2_row] != self.row_neg_poles[pole2_row] {
+                if CSP::misses_clue(self.curr_row_pos_poles[pole2_row], self.row_pos_poles[pole2_row])
+                || CSP::misses_clue(self.curr_row_neg_poles[pole2_row], self.row_neg_poles[pole2_row]) {
                     return false
                 }
             }
-            if self.curr_row_pos_poles[pole1_row] > self.row_pos_poles[pole1_row]
-                || self.curr_row_neg_poles[pole1_row] > self.row_neg_poles[pole1_row] {
+            if CSP::exceeds_clue(self.curr_row_pos_poles[pole1_row], self.row_pos_poles[pole1_row])
+                || CSP::exceeds_clue(self.curr_row_neg_poles[pole1_row], self.row_neg_poles[pole1_row]) {
                 return false
             }
-            if self.curr_row_pos_poles[pole2_row] > self.row_pos_poles[pole2_row]
-                || self.curr_row_neg_poles[pole2_row] > self.row_neg_poles[pole2_row] {
+            if CSP::exceeds_clue(self.curr_row_pos_poles[pole2_row], self.row_pos_poles[pole2_row])
+                || CSP::exceeds_clue(self.curr_row_neg_poles[pole2_row], self.row_neg_poles[pole2_row]) {
                 return false
             }
             let poles_col = var.poles[0].col;
-            let mut poles_col_all_assigned = false;
+            let mut poles_col_all_assigned = true;
             for i in 0..self.row_size {
                 poles_col_all_assigned &= self.board[i][poles_col] != BoardCell::Unassigned;
             }
             if poles_col_all_assigned {
-                if self.curr_col_pos_poles[poles_col] != self.col_pos_poles[poles_col]
-                || self.curr_col_neg_poles[poles_col] != self.col_neg_poles[poles_col] {
+                if CSP::misses_clue(self.curr_col_pos_poles[poles_col], self.col_pos_poles[poles_col])
+                || CSP::misses_clue(self.curr_col_neg_poles[poles_col], self.col_neg_poles[poles_col]) {
                     return false
                 }
             }
-            if self.curr_col_pos_poles[poles_col] > self.col_pos_poles[poles_col]
-                || self.curr_col_neg_poles[poles_col] > self.col_neg_poles[poles_col] {
+            if CSP::exceeds_clue(self.curr_col_pos_poles[poles_col], self.col_pos_poles[poles_col])
+                || CSP::exceeds_clue(self.curr_col_neg_poles[poles_col], self.col_neg_poles[poles_col]) {
                 return false
             }
         }
@@ -0,0 +1,211 @@
+use crate::clues::Clue;
+use crate::csp::*;
+use crate::png::*;
+use crate::render::*;
+
+const MISMATCH_COLOR: Rgb = [255, 220, 120];
+
+// For every clue, whether the number of poles on the board matches it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClueSatisfaction {
+    pub row_pos_poles: Vec<bool>,
+    pub row_neg_poles: Vec<bool>,
+    pub col_pos_poles: Vec<bool>,
+    pub col_neg_poles: Vec<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BoardDiff {
+    pub mismatched_variables: Vec<VariableIndex>,
+    pub left_clues: ClueSatisfaction,
+    pub right_clues: ClueSatisfaction,
+}
+
+impl ClueSatisfaction {
+    pub fn satisfied_count(&self) -> usize {
+        [&self.row_pos_poles, &self.row_neg_poles, &self.col_pos_poles, &self.col_neg_poles]
+            .iter()
+            .map(|clues| clues.iter().filter(|satisfied| **satisfied).count())
+            .sum()
+    }
+
+    pub fn total_count(&self) -> usize {
+        self.row_pos_poles.len() + self.row_neg_poles.len() + self.col_pos_poles.len() + self.col_neg_poles.len()
+    }
+}
+
+impl CSP {
+    pub fn diff(&self, left: &Assignment, right: &Assignment) -> BoardDiff {
+        let mismatched_variables = self
+            .variables
+            .iter()
+            .filter(|variable| left[variable.index] != right[variable.index])
+            .map(|variable| variable.index)
+            .collect();
+        BoardDiff {
+            mismatched_variables,
+            left_clues: self.clue_satisfaction(left),
+            right_clues: self.clue_satisfaction(right),
+        }
+    }
+
+    pub fn clue_satisfaction(&self, assignment: &Assignment) -> ClueSatisfaction {
+        let board = self.board_from_assignment(assignment);
+        let count = |cells: &mut dyn Iterator<Item = &BoardCell>, sign: BoardCell| {
+            cells.filter(|cell| **cell == sign).count() as i32
+        };
+        let satisfied = |clue: i32, count: i32| clue < 0 || clue == count;
+
+        let mut satisfaction = ClueSatisfaction {
+            row_pos_poles: Vec::new(),
+            row_neg_poles: Vec::new(),
+            col_pos_poles: Vec::new(),
+            col_neg_poles: Vec::new(),
+        };
+        for (i, row) in board.iter().enumerate() {
+            let pos = count(&mut row.iter(), BoardCell::Positive);
+            let neg = count(&mut row.iter(), BoardCell::Negative);
+            satisfaction.row_pos_poles.push(satisfied(self.row_pos_poles[i], pos));
+            satisfaction.row_neg_poles.push(satisfied(self.row_neg_poles[i], neg));
+        }
+        for j in 0..self.col_size {
+            let pos = count(&mut board.iter().map(|row| &row[j]), BoardCell::Positive);
+            let neg = count(&mut board.iter().map(|row| &row[j]), BoardCell::Negative);
+            satisfaction.col_pos_poles.push(satisfied(self.col_pos_poles[j], pos));
+            satisfaction.col_neg_poles.push(satisfied(self.col_neg_poles[j], neg));
+        }
+        satisfaction
+    }
+
+    // Converts a board into the assignment of the magnet slots, fails if the two poles of a slot
+    // do not form a valid magnet
+    pub fn assignment_from_board(&self, board: &[Vec<BoardCell>]) -> Result<Assignment, String> {
+        let mut assignment: Assignment = vec![Value::Unassigned; self.variables.len()];
+        for variable in &self.variables {
+            let pole1 = &variable.poles[0];
+            let pole2 = &variable.poles[1];
+            assignment[variable.index] = match (&board[pole1.row][pole1.col], &board[pole2.row][pole2.col]) {
+                (BoardCell::Positive, BoardCell::Negative) => Value::Pole1PositivePole2Negative,
+                (BoardCell::Negative, BoardCell::Positive) => Value::Pole2PositivePole1Negative,
+                (BoardCell::Empty, BoardCell::Empty) => Value::Empty,
+                (BoardCell::Unassigned, BoardCell::Unassigned) => Value::Unassigned,
+                _ => {
+                    return Err(format!(
+                        "Cells ({}, {}) and ({}, {}) do not form a valid magnet",
+                        pole1.row, pole1.col, pole2.row, pole2.col
+                    ))
+                }
+            };
+        }
+        Ok(assignment)
+    }
+
+    // Parses a board in the format written by print_board
+    pub fn parse_printed_board(&self, text: &str) -> Result<Assignment, String> {
+        let rows: Vec<&str> = text.lines().skip(2).take(self.row_size).collect();
+        if rows.len() != self.row_size {
+            return Err(format!("Expected {} board rows after the two clue lines", self.row_size));
+        }
+        let mut board = vec![vec![BoardCell::Empty; self.col_size]; self.row_size];
+        for (i, row) in rows.iter().enumerate() {
+            let chars: Vec<char> = row.chars().collect();
+            for (j, cell) in board[i].iter_mut().enumerate() {
+                // every cell is right aligned in its column after the two row clues
+                *cell = match chars.get(2 * PRINT_WIDTH + PRINT_WIDTH * j + PRINT_WIDTH - 1) {
+                    Some('+') => BoardCell::Positive,
+                    Some('-') => BoardCell::Negative,
+                    Some('*') => BoardCell::Unassigned,
+                    Some(' ') | None => BoardCell::Empty,
+                    Some(c) => return Err(format!("Unexpected character '{}' in row {}", c, i)),
+                };
+            }
+        }
+        self.assignment_from_board(&board)
+    }
+
+    // Prints both boards merged into one, cells where they disagree are shown as left/right
+    pub fn print_diff(&self, left: &Assignment, right: &Assignment, diff: &BoardDiff) {
+        let left_board = self.board_from_assignment(left);
+        let right_board = self.board_from_assignment(right);
+        let sign = |cell: &BoardCell| match cell {
+            BoardCell::Positive => '+',
+            BoardCell::Negative => '-',
+            BoardCell::Empty => '.',
+            BoardCell::Unassigned => '*',
+        };
+
+        let clue_text = |clue: Clue| self.clue_value(clue).to_string();
+        let cell_text = |i: usize, j: usize| {
+            if left_board[i][j] == right_board[i][j] {
+                match left_board[i][j] {
+                    BoardCell::Empty => String::new(),
+                    ref cell => sign(cell).to_string(),
+                }
+            } else {
+                format!("{}/{}", sign(&left_board[i][j]), sign(&right_board[i][j]))
+            }
+        };
+        self.print_grid(&clue_text, &cell_text);
+
+        println!();
+        println!("{} of {} magnets differ", diff.mismatched_variables.len(), self.variables.len());
+        for (name, clues) in [("left", &diff.left_clues), ("right", &diff.right_clues)] {
+            println!("{} board satisfies {} of {} clues", name, clues.satisfied_count(), clues.total_count());
+            let unsatisfied = [
+                ("row", "+", &clues.row_pos_poles),
+                ("row", "-", &clues.row_neg_poles),
+                ("column", "+", &clues.col_pos_poles),
+                ("column", "-", &clues.col_neg_poles),
+            ];
+            for (line, sign, satisfied) in unsatisfied {
+                for (index, _) in satisfied.iter().enumerate().filter(|(_, satisfied)| !**satisfied) {
+                    println!("    {} {} {} poles not satisfied", line, index, sign);
+                }
+            }
+        }
+    }
+
+    // Renders the cells both boards agree on as usual, mismatched cells are highlighted and show
+    // the left sign in the top left corner and the right sign in the bottom right corner
+    pub fn render_diff(&self, left: &Assignment, right: &Assignment) -> Canvas {
+        let left_board = self.board_from_assignment(left);
+        let right_board = self.board_from_assignment(right);
+        let mut common_board = left_board.clone();
+        for i in 0..self.row_size {
+            for j in 0..self.col_size {
+                if left_board[i][j] != right_board[i][j] {
+                    common_board[i][j] = BoardCell::Unassigned;
+                }
+            }
+        }
+
+        let mismatch = |i: usize, j: usize| {
+            if left_board[i][j] != right_board[i][j] {
+                Some(MISMATCH_COLOR)
+            } else {
+                None
+            }
+        };
+        let mut canvas = self.render_board_with(&common_board, &mismatch);
+        for i in 0..self.row_size {
+            for j in 0..self.col_size {
+                if left_board[i][j] == right_board[i][j] {
+                    continue;
+                }
+                let (x, y) = self.cell_origin(i, j);
+                let corners = [
+                    (&left_board[i][j], x + CELL_SIZE / 4 + 1, y + CELL_SIZE / 4 + 1),
+                    (&right_board[i][j], x + 3 * CELL_SIZE / 4 - 1, y + 3 * CELL_SIZE / 4 - 1),
+                ];
+                for (cell, center_x, center_y) in corners {
+                    match cell {
+                        BoardCell::Positive => canvas.draw_text(center_x, center_y, "+", 2, POSITIVE_COLOR),
+                        BoardCell::Negative => canvas.draw_text(center_x, center_y, "-", 2, NEGATIVE_COLOR),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        canvas
+    }
+}
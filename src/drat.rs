@@ -0,0 +1,370 @@
+use crate::cdcl::{Lit, ProofStep};
+use crate::cnf::Cnf;
+use crate::csp::*;
+use std::collections::HashMap;
+
+// A formula together with a DRAT proof that it has no model. For a unique puzzle the formula is
+// the encoding with the solution blocked, so the proof shows there is no second solution.
+pub struct Certificate {
+    pub solution: Option<Assignment>,
+    pub formula: Cnf,
+    pub proof: Vec<ProofStep>,
+}
+
+pub enum UniquenessProof {
+    Certified(Certificate),
+    Multiple(Assignment, Assignment),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DratSummary {
+    pub lemmas: usize,
+    // lemmas that are not implied by unit propagation and needed the resolution asymmetric
+    // tautology check on their first literal
+    pub rat_lemmas: usize,
+    pub deletions: usize,
+}
+
+// Forward DRAT checker, written separately from the solver so that it doesn't share its bugs.
+// Every lemma must be a reverse unit propagation (RUP) consequence of the clauses so far, or a
+// resolution asymmetric tautology (RAT) on its first literal, and the proof must reach the empty
+// clause. Unit propagation simply scans every active clause until nothing changes, slow but easy
+// to trust.
+struct DratChecker {
+    clauses: Vec<Vec<Lit>>,
+    active: Vec<bool>,
+    // active clauses by their sorted literals, to find the clause a deletion refers to
+    by_literals: HashMap<Vec<Lit>, Vec<usize>>,
+    // the formula contains the empty clause
+    has_empty: bool,
+    // 1 true, -1 false, 0 unassigned, indexed by variable
+    values: Vec<i8>,
+}
+
+impl CSP {
+    // Solves with the CDCL solver recording a proof. Without a solution the proof refutes the
+    // encoding, with one the solution is blocked and the solver runs again, and when it finds no
+    // other solution the proof refutes the encoding plus the blocking clause.
+    pub fn prove_uniqueness(&self) -> UniquenessProof {
+        let mut formula = self.encode_cnf();
+        let mut solver = formula.solver();
+        solver.enable_proof();
+        let solution = solver.solve().map(|model| self.decode_model(&model).expect("The encoding gives every magnet one value"));
+        if let Some(solution) = &solution {
+            let blocking = CSP::blocking_clause(solution);
+            solver.add_clause(&blocking);
+            formula.add(blocking);
+            if let Some(model) = solver.solve() {
+                let second = self.decode_model(&model).expect("The encoding gives every magnet one value");
+                return UniquenessProof::Multiple(solution.clone(), second);
+            }
+        }
+        UniquenessProof::Certified(Certificate {
+            solution,
+            formula,
+            proof: solver.proof.take().unwrap_or_default(),
+        })
+    }
+
+    // Clause that rules out exactly this assignment of the magnets
+    pub fn blocking_clause(solution: &Assignment) -> Vec<Lit> {
+        solution.iter().enumerate().map(|(var_index, value)| -CSP::magnet_literal(var_index, *value)).collect()
+    }
+
+    // Checks a certificate against this puzzle: the formula must be its encoding, optionally
+    // followed by a clause blocking a board that solves the puzzle, and the proof must refute the
+    // formula. Returns the blocked solution, None when the certificate claims there is none.
+    pub fn check_certificate(&self, formula: &Cnf, proof: &[ProofStep]) -> Result<(Option<Assignment>, DratSummary), String> {
+        let encoding = self.encode_cnf();
+        if formula.num_vars != encoding.num_vars || !formula.clauses.starts_with(&encoding.clauses) {
+            return Err(String::from("The formula is not the encoding of this puzzle"));
+        }
+        let solution = match &formula.clauses[encoding.clauses.len()..] {
+            [] => None,
+            [blocking] => Some(self.blocked_solution(blocking)?),
+            _ => return Err(String::from("The formula has more than one clause after the encoding")),
+        };
+        if let Some(solution) = &solution {
+            if !self.is_solution(solution) {
+                return Err(String::from("The blocked board doesn't solve the puzzle"));
+            }
+        }
+        let summary = check_drat(formula, proof)?;
+        Ok((solution, summary))
+    }
+
+    fn blocked_solution(&self, blocking: &[Lit]) -> Result<Assignment, String> {
+        let error = || String::from("The last clause of the formula doesn't block a board");
+        if blocking.len() != self.variables.len() {
+            return Err(error());
+        }
+        let mut solution = Vec::new();
+        for (var_index, literal) in blocking.iter().enumerate() {
+            let value = [Value::Pole1PositivePole2Negative, Value::Pole2PositivePole1Negative, Value::Empty]
+                .into_iter()
+                .find(|value| -CSP::magnet_literal(var_index, *value) == *literal)
+                .ok_or_else(error)?;
+            solution.push(value);
+        }
+        Ok(solution)
+    }
+
+    // Whether the full assignment meets every clue and never puts like poles side by side
+    pub fn is_solution(&self, assignment: &Assignment) -> bool {
+        let board = self.board_from_assignment(assignment);
+        let like_poles = |a: &BoardCell, b: &BoardCell| (*a == BoardCell::Positive || *a == BoardCell::Negative) && a == b;
+        for row in 0..self.row_size {
+            for col in 0..self.col_size {
+                if board[row][col] == BoardCell::Unassigned {
+                    return false;
+                }
+                if row + 1 < self.row_size && like_poles(&board[row][col], &board[row + 1][col]) {
+                    return false;
+                }
+                if col + 1 < self.col_size && like_poles(&board[row][col], &board[row][col + 1]) {
+                    return false;
+                }
+            }
+        }
+        let clues = self.clue_satisfaction(assignment);
+        clues.satisfied_count() == clues.total_count()
+    }
+}
+
+// Verifies that the proof derives the empty clause from the formula
+pub fn check_drat(formula: &Cnf, proof: &[ProofStep]) -> Result<DratSummary, String> {
+    let mut checker = DratChecker {
+        clauses: Vec::new(),
+        active: Vec::new(),
+        by_literals: HashMap::new(),
+        has_empty: false,
+        values: vec![0; formula.num_vars + 1],
+    };
+    for clause in &formula.clauses {
+        checker.add(clause);
+    }
+    let mut summary = DratSummary::default();
+    for (line, step) in proof.iter().enumerate() {
+        match step {
+            ProofStep::Add(lemma) => {
+                if lemma.iter().any(|literal| literal.unsigned_abs() as usize > formula.num_vars) {
+                    return Err(format!("Lemma {} of the proof uses a variable the formula doesn't have", line + 1));
+                }
+                if !checker.is_rup(lemma) {
+                    if !checker.is_rat(lemma) {
+                        return Err(format!("Lemma {} of the proof is neither RUP nor RAT: {:?}", line + 1, lemma));
+                    }
+                    summary.rat_lemmas += 1;
+                }
+                summary.lemmas += 1;
+                if lemma.is_empty() {
+                    return Ok(summary);
+                }
+                checker.add(lemma);
+            }
+            ProofStep::Delete(clause) => {
+                checker.delete(clause);
+                summary.deletions += 1;
+            }
+        }
+    }
+    Err(String::from("The proof never derives the empty clause"))
+}
+
+// Reads a DRAT proof in the text format
+pub fn parse_drat(text: &str) -> Result<Vec<ProofStep>, String> {
+    let mut proof = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('c') {
+            continue;
+        }
+        let (deletion, literals) = match line.strip_prefix('d') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let mut clause: Vec<Lit> = literals
+            .split_whitespace()
+            .map(|literal| literal.parse().map_err(|_| format!("Bad literal '{}' in the proof", literal)))
+            .collect::<Result<_, _>>()?;
+        if clause.pop() != Some(0) || clause.contains(&0) {
+            return Err(format!("Proof line '{}' must end with a single 0", line));
+        }
+        proof.push(if deletion { ProofStep::Delete(clause) } else { ProofStep::Add(clause) });
+    }
+    Ok(proof)
+}
+
+impl DratChecker {
+    fn add(&mut self, clause: &[Lit]) {
+        let mut literals = clause.to_vec();
+        literals.sort_unstable();
+        literals.dedup();
+        if literals.is_empty() {
+            self.has_empty = true;
+        }
+        self.by_literals.entry(literals.clone()).or_default().push(self.clauses.len());
+        self.clauses.push(literals);
+        self.active.push(true);
+    }
+
+    // Deleting a clause that isn't there is ignored, like other checkers do
+    fn delete(&mut self, clause: &[Lit]) {
+        let mut key = clause.to_vec();
+        key.sort_unstable();
+        key.dedup();
+        if let Some(clause_index) = self.by_literals.get_mut(&key).and_then(|indices| indices.pop()) {
+            self.active[clause_index] = false;
+        }
+    }
+
+    // Assigning the negation of every literal of the lemma leads unit propagation to a conflict
+    fn is_rup(&mut self, lemma: &[Lit]) -> bool {
+        if self.has_empty {
+            return true;
+        }
+        for value in self.values.iter_mut() {
+            *value = 0;
+        }
+        for literal in lemma {
+            match self.value(*literal) {
+                // the lemma contains both signs of a variable
+                1 => return true,
+                -1 => {}
+                _ => self.assign(-literal),
+            }
+        }
+        self.propagate_to_conflict()
+    }
+
+    // Every resolvent of the lemma on its first literal with a clause containing the opposite
+    // literal is RUP
+    fn is_rat(&mut self, lemma: &[Lit]) -> bool {
+        let pivot = match lemma.first() {
+            Some(pivot) => *pivot,
+            None => return false,
+        };
+        let partners: Vec<usize> = (0..self.clauses.len()).filter(|index| self.active[*index] && self.clauses[*index].contains(&-pivot)).collect();
+        partners.into_iter().all(|index| {
+            let mut resolvent = lemma.to_vec();
+            resolvent.extend(self.clauses[index].iter().filter(|literal| **literal != -pivot));
+            self.is_rup(&resolvent)
+        })
+    }
+
+    // Assigns the last literal of every clause whose other literals are all false, until a clause
+    // has all its literals false or no clause is unit any more
+    fn propagate_to_conflict(&mut self) -> bool {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for clause_index in 0..self.clauses.len() {
+                if !self.active[clause_index] {
+                    continue;
+                }
+                let mut unassigned = None;
+                let mut open = 0;
+                let mut satisfied = false;
+                for literal in &self.clauses[clause_index] {
+                    match self.value(*literal) {
+                        1 => {
+                            satisfied = true;
+                            break;
+                        }
+                        0 => {
+                            unassigned = Some(*literal);
+                            open += 1;
+                        }
+                        _ => {}
+                    }
+                }
+                if satisfied || open > 1 {
+                    continue;
+                }
+                match unassigned {
+                    Some(literal) => {
+                        self.assign(literal);
+                        changed = true;
+                    }
+                    None => return true,
+                }
+            }
+        }
+        false
+    }
+
+    fn value(&self, literal: Lit) -> i8 {
+        let value = self.values[literal.unsigned_abs() as usize];
+        if literal < 0 {
+            -value
+        } else {
+            value
+        }
+    }
+
+    fn assign(&mut self, literal: Lit) {
+        self.values[literal.unsigned_abs() as usize] = if literal < 0 { -1 } else { 1 };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Four pigeons in three holes, pigeon p in hole h is variable 3p + h + 1. Unit propagation
+    // alone can't refute it.
+    fn pigeonhole() -> Cnf {
+        let mut formula = Cnf { num_vars: 12, clauses: Vec::new() };
+        let var = |pigeon: Lit, hole: Lit| 3 * pigeon + hole + 1;
+        for pigeon in 0..4 {
+            formula.add((0..3).map(|hole| var(pigeon, hole)).collect());
+        }
+        for hole in 0..3 {
+            for first in 0..4 {
+                for second in first + 1..4 {
+                    formula.add(vec![-var(first, hole), -var(second, hole)]);
+                }
+            }
+        }
+        formula
+    }
+
+    fn solver_proof(formula: &Cnf) -> Vec<ProofStep> {
+        let mut solver = formula.solver();
+        solver.enable_proof();
+        assert!(solver.solve().is_none());
+        solver.proof.take().unwrap_or_default()
+    }
+
+    #[test]
+    fn accepts_the_solver_proof() {
+        let formula = pigeonhole();
+        let proof = solver_proof(&formula);
+        assert!(check_drat(&formula, &proof).is_ok());
+        // and once more through the text format
+        let proof = parse_drat(&crate::cdcl::drat_text(&proof)).unwrap();
+        assert!(check_drat(&formula, &proof).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_truncated_proof() {
+        let formula = pigeonhole();
+        let proof = solver_proof(&formula);
+        let end = proof.iter().rposition(|step| *step == ProofStep::Add(Vec::new())).unwrap();
+        assert!(check_drat(&formula, &proof[..end]).is_err_and(|error| error.contains("never derives")));
+        // the empty clause alone doesn't follow by unit propagation
+        assert!(check_drat(&formula, &proof[end..]).is_err_and(|error| error.contains("neither RUP nor RAT")));
+    }
+
+    #[test]
+    fn rejects_an_edited_proof() {
+        let formula = pigeonhole();
+        let mut proof = solver_proof(&formula);
+        // pigeon 0 in hole 0 is neither RUP nor RAT
+        proof.insert(0, ProofStep::Add(vec![1]));
+        assert!(check_drat(&formula, &proof).is_err_and(|error| error.contains("neither RUP nor RAT")));
+        // nor is a lemma over a variable the formula doesn't have
+        proof[0] = ProofStep::Add(vec![13]);
+        assert!(check_drat(&formula, &proof).is_err_and(|error| error.contains("doesn't have")));
+    }
+}
@@ -5,19 +5,19 @@ impl CSP {
 
     pub fn forward_checking(
         &self,
-        domains: &Domain,
+        domains: &mut Domain,
+        trail: &mut Trail,
         assignment: &Assignment,
         mut arc_queue: VecDeque<ConstraintArc>
-    ) -> (bool, Domain) {
-        let mut inferred_domains = domains.clone();
+    ) -> bool {
         while !arc_queue.is_empty() {
             if let Some(constraint_arc) = arc_queue.pop_front() {
-                let (feasible, _) = self.revise(&constraint_arc, &mut inferred_domains, assignment);
+                let (feasible, _) = self.revise(&constraint_arc, domains, trail, assignment);
                 if !feasible {
-                    return (false, vec![]);
+                    return false;
                 }
             }
         }
-        (true, inferred_domains)
+        true
     }
 }
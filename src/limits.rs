@@ -0,0 +1,154 @@
+use crate::csp::*;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Bounds on a single search, every limit that is None is not checked
+#[derive(Debug, Clone, Default)]
+pub struct SolveLimits {
+    pub deadline: Option<Instant>,
+    // assignments tried
+    pub max_nodes: Option<usize>,
+    // assignments undone because the subtree below them had no solution
+    pub max_backtracks: Option<usize>,
+    // set from another thread to stop the search at the next node
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Deadline,
+    Nodes,
+    Backtracks,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SolveStats {
+    pub nodes: usize,
+    pub backtracks: usize,
+    pub restarts: usize,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub enum SolveResult {
+    Solved(Assignment, SolveStats),
+    // the whole search space was explored without finding a solution
+    Unsat(SolveStats),
+    // the search stopped early, so the puzzle may or may not have a solution
+    LimitReached(Limit, SolveStats),
+}
+
+// Limits of a running search together with what it used so far
+pub struct SearchBudget<'a> {
+    limits: &'a SolveLimits,
+    start: Instant,
+    stats: SolveStats,
+    pub reached: Option<Limit>,
+}
+
+impl SolveLimits {
+    pub fn with_timeout(mut self, timeout: Duration) -> SolveLimits {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> SolveLimits {
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+
+    pub fn with_max_backtracks(mut self, max_backtracks: usize) -> SolveLimits {
+        self.max_backtracks = Some(max_backtracks);
+        self
+    }
+}
+
+impl<'a> SearchBudget<'a> {
+    pub fn new(limits: &'a SolveLimits) -> SearchBudget<'a> {
+        SearchBudget {
+            limits,
+            start: Instant::now(),
+            stats: SolveStats::default(),
+            reached: None,
+        }
+    }
+
+    // Records the values tried and undone and the restarts so far, returns false once any limit is
+    // reached
+    pub fn check(&mut self, nodes: usize, backtracks: usize, restarts: usize) -> bool {
+        self.stats.nodes = nodes;
+        self.stats.backtracks = backtracks;
+        self.stats.restarts = restarts;
+        if self.reached.is_some() {
+            return false;
+        }
+        if self.limits.max_nodes.is_some_and(|max_nodes| nodes > max_nodes) {
+            self.reached = Some(Limit::Nodes);
+        } else if self.limits.max_backtracks.is_some_and(|max_backtracks| backtracks > max_backtracks) {
+            self.reached = Some(Limit::Backtracks);
+        } else if self.limits.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            self.reached = Some(Limit::Deadline);
+        } else if self.limits.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+            self.reached = Some(Limit::Cancelled);
+        }
+        self.reached.is_none()
+    }
+
+    pub fn stats(&self) -> SolveStats {
+        SolveStats {
+            elapsed: self.start.elapsed(),
+            ..self.stats
+        }
+    }
+
+    // Turns the outcome of the search into a result
+    pub fn finish(&self, solution: Option<Assignment>) -> SolveResult {
+        match (solution, self.reached) {
+            (Some(solution), _) => SolveResult::Solved(solution, self.stats()),
+            (None, Some(limit)) => SolveResult::LimitReached(limit, self.stats()),
+            (None, None) => SolveResult::Unsat(self.stats()),
+        }
+    }
+}
+
+impl SolveResult {
+    pub fn solution(self) -> Option<Assignment> {
+        match self {
+            SolveResult::Solved(solution, _) => Some(solution),
+            _ => None,
+        }
+    }
+
+    pub fn stats(&self) -> &SolveStats {
+        match self {
+            SolveResult::Solved(_, stats) | SolveResult::Unsat(stats) | SolveResult::LimitReached(_, stats) => stats,
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Deadline => write!(f, "deadline reached"),
+            Limit::Nodes => write!(f, "node limit reached"),
+            Limit::Backtracks => write!(f, "backtrack limit reached"),
+            Limit::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl fmt::Display for SolveStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} backtracks, {} restarts in {:.3}s",
+            self.nodes,
+            self.backtracks,
+            self.restarts,
+            self.elapsed.as_secs_f64()
+        )
+    }
+}
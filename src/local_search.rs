@@ -0,0 +1,253 @@
+use crate::clues::Clue;
+use crate::csp::*;
+use crate::limits::*;
+use crate::rng::Rng;
+
+// Settings of the min-conflicts search
+#[derive(Debug, Clone, Copy)]
+pub struct LocalSearchParams {
+    // probability of moving a conflicted magnet to a random value instead of the best one
+    pub noise: f64,
+    // number of flips a magnet may not return to the value it just left
+    pub tabu_tenure: usize,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalSearchResult {
+    // the assignment with the fewest violations seen, a solution when violations is 0
+    pub assignment: Assignment,
+    pub violations: usize,
+    // nodes counts the flips
+    pub stats: SolveStats,
+    // the limit that stopped the search before it found a solution
+    pub limit: Option<Limit>,
+}
+
+impl Default for LocalSearchParams {
+    fn default() -> LocalSearchParams {
+        LocalSearchParams {
+            noise: 0.1,
+            tabu_tenure: 10,
+            seed: 0,
+        }
+    }
+}
+
+// A full assignment with its board and pole counts, kept up to date flip by flip so that the
+// violations a flip adds or removes can be counted locally
+struct LocalState<'a> {
+    csp: &'a CSP,
+    assignment: Assignment,
+    board: Vec<Vec<BoardCell>>,
+    // pole counts of every line, indexed like the clue vectors
+    row_pos: Vec<i32>,
+    row_neg: Vec<i32>,
+    col_pos: Vec<i32>,
+    col_neg: Vec<i32>,
+    // cells next to each pole of each magnet, without the magnet's other pole
+    neighbors: Vec<[Vec<Point>; 2]>,
+}
+
+const VALUES: [Value; 3] = [Value::Pole1PositivePole2Negative, Value::Pole2PositivePole1Negative, Value::Empty];
+
+impl CSP {
+    // Incomplete search: starts from a random full assignment and keeps moving one magnet of a
+    // violated constraint to the value with the fewest violations, where a violation is a pair of
+    // touching like poles or one pole too many or too few for a clue. Values a magnet just left
+    // are tabu for a while unless they lead to a new best, and with probability noise a random
+    // value is taken instead. Stops at a solution or at a limit, max_nodes bounds the flips and
+    // max_backtracks is ignored since nothing is undone. Can't prove that a puzzle has no solution.
+    pub fn local_search(&self, params: &LocalSearchParams, limits: &SolveLimits) -> LocalSearchResult {
+        let mut budget = SearchBudget::new(limits);
+        let mut rng = Rng::new(params.seed);
+        let assignment = self.variables.iter().map(|_| VALUES[rng.below(VALUES.len())]).collect();
+        let mut state = LocalState::new(self, assignment);
+        let mut violations = state.violations();
+        let mut best = (violations, state.assignment.clone());
+        // flip number until which a value is tabu for a magnet
+        let mut tabu_until = vec![[0usize; 3]; self.variables.len()];
+        let mut flips = 0;
+
+        while violations > 0 && budget.check(flips, 0, 0) {
+            let conflicted: Vec<VariableIndex> = (0..self.variables.len()).filter(|var_index| state.is_conflicted(*var_index)).collect();
+            let var_index = conflicted[rng.below(conflicted.len())];
+            let current = state.assignment[var_index];
+            let others: Vec<Value> = VALUES.iter().copied().filter(|value| *value != current).collect();
+
+            let value = if rng.below(1_000_000) < (params.noise * 1_000_000.0) as usize {
+                others[rng.below(others.len())]
+            } else {
+                let mut candidates = Vec::new();
+                let mut best_delta = i64::MAX;
+                for value in others.iter().copied() {
+                    let delta = state.delta(var_index, value);
+                    let tabu = tabu_until[var_index][value_position(value)] > flips;
+                    // aspiration: a tabu value is allowed when it beats the best assignment
+                    if tabu && violations as i64 + delta >= best.0 as i64 {
+                        continue;
+                    }
+                    if delta < best_delta {
+                        best_delta = delta;
+                        candidates.clear();
+                    }
+                    if delta == best_delta {
+                        candidates.push(value);
+                    }
+                }
+                if candidates.is_empty() {
+                    others[rng.below(others.len())]
+                } else {
+                    candidates[rng.below(candidates.len())]
+                }
+            };
+
+            violations = (violations as i64 + state.delta(var_index, value)) as usize;
+            state.set(var_index, value);
+            flips += 1;
+            tabu_until[var_index][value_position(current)] = flips + params.tabu_tenure;
+            if violations < best.0 {
+                best = (violations, state.assignment.clone());
+            }
+        }
+
+        budget.check(flips, 0, 0);
+        LocalSearchResult {
+            assignment: best.1,
+            violations: best.0,
+            stats: budget.stats(),
+            limit: if best.0 == 0 { None } else { budget.reached },
+        }
+    }
+
+    // Cells sharing a side with the cell, except the other pole of its magnet
+    pub fn touching_cells(&self, cell: &Point, same_variable_cell: &Point) -> Vec<Point> {
+        let (row, col) = (cell.row as i64, cell.col as i64);
+        [(row - 1, col), (row + 1, col), (row, col - 1), (row, col + 1)]
+            .into_iter()
+            .filter(|(row, col)| *row >= 0 && *col >= 0 && (*row as usize) < self.row_size && (*col as usize) < self.col_size)
+            .map(|(row, col)| Point { row: row as usize, col: col as usize })
+            .filter(|point| point != same_variable_cell)
+            .collect()
+    }
+}
+
+impl<'a> LocalState<'a> {
+    fn new(csp: &'a CSP, assignment: Assignment) -> LocalState<'a> {
+        let board = csp.board_from_assignment(&assignment);
+        let count = |clue: Clue| csp.clue_count(clue, &board);
+        let neighbors = csp
+            .variables
+            .iter()
+            .map(|variable| {
+                let (pole1, pole2) = (&variable.poles[0], &variable.poles[1]);
+                [csp.touching_cells(pole1, pole2), csp.touching_cells(pole2, pole1)]
+            })
+            .collect();
+        LocalState {
+            row_pos: (0..csp.row_size).map(|i| count(Clue::RowPositive(i))).collect(),
+            row_neg: (0..csp.row_size).map(|i| count(Clue::RowNegative(i))).collect(),
+            col_pos: (0..csp.col_size).map(|j| count(Clue::ColPositive(j))).collect(),
+            col_neg: (0..csp.col_size).map(|j| count(Clue::ColNegative(j))).collect(),
+            csp,
+            assignment,
+            board,
+            neighbors,
+        }
+    }
+
+    // All violations of the assignment
+    fn violations(&self) -> usize {
+        let mut violations = 0;
+        for row in 0..self.csp.row_size {
+            for col in 0..self.csp.col_size {
+                let cell = &self.board[row][col];
+                if row + 1 < self.csp.row_size && like_poles(cell, &self.board[row + 1][col]) {
+                    violations += 1;
+                }
+                if col + 1 < self.csp.col_size && like_poles(cell, &self.board[row][col + 1]) {
+                    violations += 1;
+                }
+            }
+        }
+        let rows: Vec<usize> = (0..self.csp.row_size).collect();
+        let cols: Vec<usize> = (0..self.csp.col_size).collect();
+        violations + self.line_deviation(&rows, &cols)
+    }
+
+    // Violations the magnet takes part in: like poles touching its poles and the deviations of
+    // the clues of its lines
+    fn local_violations(&self, var_index: VariableIndex) -> usize {
+        let poles = &self.csp.variables[var_index].poles;
+        let mut violations = 0;
+        for (pole, neighbors) in poles.iter().zip(&self.neighbors[var_index]) {
+            let cell = &self.board[pole.row][pole.col];
+            violations += neighbors.iter().filter(|neighbor| like_poles(cell, &self.board[neighbor.row][neighbor.col])).count();
+        }
+        let mut rows = vec![poles[0].row, poles[1].row];
+        let mut cols = vec![poles[0].col, poles[1].col];
+        rows.dedup();
+        cols.dedup();
+        violations + self.line_deviation(&rows, &cols)
+    }
+
+    fn line_deviation(&self, rows: &[usize], cols: &[usize]) -> usize {
+        let deviation = |count: i32, clue: i32| if clue < 0 { 0 } else { (count - clue).unsigned_abs() as usize };
+        let csp = self.csp;
+        let rows: usize = rows.iter().map(|i| deviation(self.row_pos[*i], csp.row_pos_poles[*i]) + deviation(self.row_neg[*i], csp.row_neg_poles[*i])).sum();
+        let cols: usize = cols.iter().map(|j| deviation(self.col_pos[*j], csp.col_pos_poles[*j]) + deviation(self.col_neg[*j], csp.col_neg_poles[*j])).sum();
+        rows + cols
+    }
+
+    fn is_conflicted(&self, var_index: VariableIndex) -> bool {
+        self.local_violations(var_index) > 0
+    }
+
+    // Change of the total violations if the magnet took the value
+    fn delta(&mut self, var_index: VariableIndex, value: Value) -> i64 {
+        let current = self.assignment[var_index];
+        let before = self.local_violations(var_index) as i64;
+        self.set(var_index, value);
+        let after = self.local_violations(var_index) as i64;
+        self.set(var_index, current);
+        after - before
+    }
+
+    fn set(&mut self, var_index: VariableIndex, value: Value) {
+        let poles = self.csp.variables[var_index].poles.clone();
+        let signs = match value {
+            Value::Pole1PositivePole2Negative => [BoardCell::Positive, BoardCell::Negative],
+            Value::Pole2PositivePole1Negative => [BoardCell::Negative, BoardCell::Positive],
+            _ => [BoardCell::Empty, BoardCell::Empty],
+        };
+        for (pole, sign) in poles.iter().zip(signs) {
+            self.count(pole, -1);
+            self.board[pole.row][pole.col] = sign;
+            self.count(pole, 1);
+        }
+        self.assignment[var_index] = value;
+    }
+
+    // Adds change to the counts of the lines of the cell for the pole it holds
+    fn count(&mut self, cell: &Point, change: i32) {
+        match self.board[cell.row][cell.col] {
+            BoardCell::Positive => {
+                self.row_pos[cell.row] += change;
+                self.col_pos[cell.col] += change;
+            }
+            BoardCell::Negative => {
+                self.row_neg[cell.row] += change;
+                self.col_neg[cell.col] += change;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn like_poles(cell: &BoardCell, other: &BoardCell) -> bool {
+    *cell != BoardCell::Empty && *cell != BoardCell::Unassigned && cell == other
+}
+
+fn value_position(value: Value) -> usize {
+    VALUES.iter().position(|candidate| *candidate == value).unwrap_or(0)
+}
@@ -6,16 +6,16 @@ impl CSP {
     // AC3
     pub fn maintaining_arc_consistency(
         &self,
-        domains: &Domain,
+        domains: &mut Domain,
+        trail: &mut Trail,
         assignment: &Assignment,
         mut arc_queue: VecDeque<ConstraintArc>
-    ) -> (bool, Domain) {
-        let mut inferred_domains = domains.clone();
+    ) -> bool {
         while !arc_queue.is_empty() {
             if let Some(constraint_arc) = arc_queue.pop_front() {
-                let (feasible, revised) = self.revise(&constraint_arc, &mut inferred_domains, assignment);
+                let (feasible, revised) = self.revise(&constraint_arc, domains, trail, assignment);
                 if !feasible {
-                    return (false, vec![]);
+                    return false;
                 }
                 if revised {
                     self.generate_arc_constraints(constraint_arc.xi, assignment, &mut arc_queue, constraint_arc.xj);
@@ -23,7 +23,7 @@ impl CSP {
 
             }
         }
-        (true, inferred_domains)
+        true
     }
 
 }
@@ -1,76 +1,882 @@
+mod assumptions;
+mod backbone;
+mod backjumping;
+mod bigint;
+mod cdcl;
+mod checkpoint;
+mod clues;
+mod cnf;
+mod cp_models;
 mod csp;
+mod diff;
+mod drat;
 mod fc;
+mod limits;
+mod local_search;
 mod mac;
+mod marginals;
+mod max_csp;
+mod mip;
+mod model_count;
+mod optimize;
+mod parallel;
+mod portfolio;
+mod png;
+mod render;
+mod restarts;
+mod repair;
+mod rng;
+mod solutions;
+mod solver;
+#[cfg(test)]
+mod test_puzzles;
 
-use crate::csp::CSP;
+use crate::assumptions::{AssumptionResult, CellAssumption};
+use crate::csp::{Assignment, InferenceMode, Value, CSP};
+use crate::limits::{Limit, SolveLimits, SolveResult};
+use crate::solutions::Uniqueness;
+use crate::solver::{Progress, Step};
 use std::error::Error;
 
+#[derive(Default)]
+struct Options {
+    test_case_path: String,
+    // write the unsolved puzzle as a png before solving
+    puzzle_png_path: Option<String>,
+    // write the solved board as a png
+    png_path: Option<String>,
+    // two boards to compare, each is either a printed board file or an inference mode to solve with
+    diff: Option<(String, String)>,
+    inference_mode: InferenceMode,
+    // jump back to the variable to blame on dead ends, with any inference mode
+    backjumping: bool,
+    // stream every solution (None) or the first n (Some(n)) instead of stopping at the first one
+    enumerate: Option<Option<usize>>,
+    // only count the solutions, up to an optional limit
+    count: Option<Option<usize>>,
+    uniqueness: bool,
+    // count with the row sweep dynamic program instead of the search
+    count_models: bool,
+    // count with both and compare
+    cross_check: bool,
+    backbone: bool,
+    marginals: bool,
+    // enumerate up to this many solutions for exact marginals before falling back to sampling
+    exact_limit: Option<usize>,
+    samples: Option<usize>,
+    // break search ties randomly from this seed, also seeds the sampling of --marginals
+    seed: Option<u64>,
+    restart_policy: Option<restarts::RestartPolicy>,
+    // cells whose sign is fixed for this run only
+    assumptions: Vec<CellAssumption>,
+    // explain why the puzzle has no solution
+    diagnose: bool,
+    // find the closest board treating the clues as weighted soft constraints
+    max_csp: bool,
+    clue_weights: max_csp::ClueWeights,
+    // propose clue edits or givens for a puzzle without a unique solution
+    repair: bool,
+    max_edits: Option<usize>,
+    max_suggestions: Option<usize>,
+    // find the solution with the best objective value
+    objective: Option<optimize::Objective>,
+    minimize: bool,
+    // bounds on the search, max_nodes also bounds --optimize
+    max_nodes: Option<usize>,
+    max_backtracks: Option<usize>,
+    timeout: Option<f64>,
+    // print the node and backtrack counts of the search
+    stats: bool,
+    // print every step of the search
+    trace: bool,
+    // save the state of --count and --all runs to this file, or resume from one
+    checkpoint_path: Option<String>,
+    checkpoint_every: Option<f64>,
+    resume_path: Option<String>,
+    // search with this many threads
+    threads: Option<usize>,
+    // search with min-conflicts instead of backtracking
+    local_search: bool,
+    noise: Option<f64>,
+    tabu_tenure: Option<usize>,
+    // write the puzzle as a DIMACS CNF formula
+    dimacs_path: Option<String>,
+    // solve with the SAT encoding and the built-in CDCL solver
+    sat: bool,
+    // decode the output of an external SAT solver run on the --dimacs formula
+    sat_model_path: Option<String>,
+    // write the puzzle as a CPLEX LP or OPB model
+    lp_path: Option<String>,
+    opb_path: Option<String>,
+    // decode the solution file of a MIP or pseudo-Boolean solver run on those models
+    lp_solution_path: Option<String>,
+    opb_solution_path: Option<String>,
+    // write the puzzle as a MiniZinc model and data file (<prefix>.mzn, <prefix>.dzn) or an XCSP3 instance
+    minizinc_prefix: Option<String>,
+    xcsp3_path: Option<String>,
+    // decode the output of a MiniZinc or XCSP3 solver run on those models
+    minizinc_solution_path: Option<String>,
+    xcsp3_solution_path: Option<String>,
+    // write a uniqueness or unsatisfiability certificate to <prefix>.cnf and <prefix>.drat
+    prove_prefix: Option<String>,
+    // check such a certificate against the puzzle
+    check_proof_prefix: Option<String>,
+    // race these configurations against each other, empty for the default set
+    portfolio: Option<Vec<portfolio::PortfolioConfig>>,
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let test_case_path = std::env::args()
-        .nth(1)
-        .expect("Please provide a test case path as command line argument.");
+    let options = match parse_options(std::env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
 
-    let mut csp = init_problem(test_case_path).expect("Couldn't parse input");
-    if let Some(_) = csp.solve() {
+    let mut csp = match init_problem(options.test_case_path.clone(), options.inference_mode) {
+        Ok(csp) => csp,
+        Err(e) => {
+            eprintln!("Couldn't parse input '{}': {}", options.test_case_path, e);
+            std::process::exit(2);
+        }
+    };
+    csp.seed = options.seed;
+    csp.backjumping = options.backjumping;
+    if let Some(policy) = options.restart_policy {
+        csp.restart_policy = policy;
+        // restarting a deterministic search would only repeat it
+        csp.seed = csp.seed.or(Some(0));
+    }
+    if let Some(path) = &options.puzzle_png_path {
+        csp.render_board(&csp.board).save_png(path)?;
+    }
+    if let Some((left, right)) = &options.diff {
+        let left = load_board(&mut csp, left)?;
+        let right = load_board(&mut csp, right)?;
+        let diff = csp.diff(&left, &right);
+        csp.print_diff(&left, &right, &diff);
+        if let Some(path) = &options.png_path {
+            csp.render_diff(&left, &right).save_png(path)?;
+        }
+        return Ok(());
+    }
+    // every export asked for is written, then the run ends
+    let mut exported = false;
+    if let Some(path) = &options.dimacs_path {
+        std::fs::write(path, csp.dimacs())?;
+        exported = true;
+    }
+    if let Some(path) = &options.lp_path {
+        std::fs::write(path, csp.to_lp())?;
+        exported = true;
+    }
+    if let Some(path) = &options.opb_path {
+        std::fs::write(path, csp.to_opb())?;
+        exported = true;
+    }
+    if let Some(prefix) = &options.minizinc_prefix {
+        std::fs::write(format!("{}.mzn", prefix), csp.minizinc_model())?;
+        std::fs::write(format!("{}.dzn", prefix), csp.minizinc_data())?;
+        exported = true;
+    }
+    if let Some(path) = &options.xcsp3_path {
+        std::fs::write(path, csp.xcsp3())?;
+        exported = true;
+    }
+    if exported {
+        return Ok(());
+    }
+    if let Some(path) = &options.sat_model_path {
+        let num_vars = csp.encode_cnf().num_vars;
+        match cnf::parse_sat_model(&std::fs::read_to_string(path)?, num_vars)? {
+            Some(model) => {
+                let solution = csp.decode_model(&model)?;
+                csp.board = csp.board_from_assignment(&solution);
+                csp.print_board();
+            }
+            None => println!("No solution"),
+        }
+        return Ok(());
+    }
+    let solution_paths = [
+        &options.lp_solution_path,
+        &options.opb_solution_path,
+        &options.minizinc_solution_path,
+        &options.xcsp3_solution_path,
+    ];
+    if solution_paths.iter().any(|path| path.is_some()) {
+        let solution = if let Some(path) = &options.lp_solution_path {
+            csp.decode_lp_solution(&std::fs::read_to_string(path)?)?
+        } else if let Some(path) = &options.opb_solution_path {
+            csp.decode_opb_solution(&std::fs::read_to_string(path)?)?
+        } else if let Some(path) = &options.minizinc_solution_path {
+            csp.decode_minizinc_solution(&std::fs::read_to_string(path)?)?
+        } else if let Some(path) = &options.xcsp3_solution_path {
+            csp.decode_xcsp3_solution(&std::fs::read_to_string(path)?)?
+        } else {
+            None
+        };
+        match solution {
+            Some(solution) => {
+                csp.print_cells(&csp.board_from_assignment(&solution));
+                if !csp.is_solution(&solution) {
+                    return Err("The decoded board doesn't solve the puzzle".into());
+                }
+                println!("The decoded board solves the puzzle");
+            }
+            None => println!("No solution"),
+        }
+        return Ok(());
+    }
+    if let Some(prefix) = &options.prove_prefix {
+        match csp.prove_uniqueness() {
+            drat::UniquenessProof::Multiple(first, second) => {
+                println!("Multiple solutions, two of them differ in:");
+                let diff = csp.diff(&first, &second);
+                csp.print_diff(&first, &second, &diff);
+            }
+            drat::UniquenessProof::Certified(certificate) => {
+                let comments = vec![
+                    format!("ravenous-polarity certificate for {}", options.test_case_path),
+                    String::from("the puzzle encoding, followed by a clause blocking the solution if there is one"),
+                ];
+                std::fs::write(format!("{}.cnf", prefix), certificate.formula.to_dimacs(&comments))?;
+                std::fs::write(format!("{}.drat", prefix), cdcl::drat_text(&certificate.proof))?;
+                match &certificate.solution {
+                    Some(solution) => {
+                        println!("Unique solution");
+                        csp.print_cells(&csp.board_from_assignment(solution));
+                    }
+                    None => println!("No solution"),
+                }
+                println!("Certificate written to {0}.cnf and {0}.drat", prefix);
+            }
+        }
+        return Ok(());
+    }
+    if let Some(prefix) = &options.check_proof_prefix {
+        let formula = cnf::parse_dimacs(&std::fs::read_to_string(format!("{}.cnf", prefix))?)?;
+        let proof = drat::parse_drat(&std::fs::read_to_string(format!("{}.drat", prefix))?)?;
+        let (solution, summary) = csp.check_certificate(&formula, &proof)?;
+        match &solution {
+            Some(solution) => {
+                println!("Verified: this is the only solution");
+                csp.print_cells(&csp.board_from_assignment(solution));
+            }
+            None => println!("Verified: the puzzle has no solution"),
+        }
+        println!("{} lemmas checked, {} of them by RAT, {} deletions", summary.lemmas, summary.rat_lemmas, summary.deletions);
+        return Ok(());
+    }
+    if options.sat {
+        let result = csp.solve_sat();
+        match &result.solution {
+            Some(solution) => {
+                csp.board = csp.board_from_assignment(solution);
+                csp.print_board();
+                if let Some(path) = &options.png_path {
+                    csp.render_board(&csp.board).save_png(path)?;
+                }
+            }
+            None => println!("No solution"),
+        }
+        if options.stats {
+            println!("{} decisions, {} conflicts, {} propagations, {} restarts", result.decisions, result.conflicts, result.propagations, result.restarts);
+        }
+        return Ok(());
+    }
+    if let Some(limit) = options.count {
+        match run_solutions(&mut csp, limit, false, &options)? {
+            (count, None) => println!("{} solutions", count),
+            (count, Some(stopped)) => println!("Search stopped, {}: {} solutions so far", stopped, count),
+        }
+        return Ok(());
+    }
+    if options.count_models {
+        println!("{} solutions", csp.count_models());
+        return Ok(());
+    }
+    if options.cross_check {
+        let models = csp.count_models();
+        let solutions = csp.count_solutions(None);
+        let sat = csp.count_sat();
+        println!("row sweep: {} solutions", models);
+        println!("backtracking: {} solutions", solutions);
+        println!("SAT: {} solutions", sat);
+        if models != bigint::BigUint::from(solutions as u64) || sat != solutions {
+            return Err("The counts disagree".into());
+        }
+        return Ok(());
+    }
+    if options.backbone {
+        match csp.backbone() {
+            Some(backbone) => {
+                csp.print_backbone(&backbone);
+                if let Some(path) = &options.png_path {
+                    csp.render_backbone(&backbone).save_png(path)?;
+                }
+            }
+            None => println!("No solution"),
+        }
+        return Ok(());
+    }
+    if options.marginals {
+        let exact_limit = options.exact_limit.unwrap_or(10000);
+        let samples = options.samples.unwrap_or(1000);
+        match csp.marginals(exact_limit, samples, options.seed.unwrap_or(0)) {
+            Some(marginals) => {
+                csp.print_marginals(&marginals);
+                if let Some(path) = &options.png_path {
+                    csp.render_marginals(&marginals).save_png(path)?;
+                }
+            }
+            None => println!("No solution"),
+        }
+        return Ok(());
+    }
+    if !options.assumptions.is_empty() {
+        match csp.solve_with_assumptions(&options.assumptions)? {
+            AssumptionResult::Sat(solution) => {
+                csp.print_cells(&csp.board_from_assignment(&solution));
+                if let Some(path) = &options.png_path {
+                    csp.render_board(&csp.board_from_assignment(&solution)).save_png(path)?;
+                }
+            }
+            AssumptionResult::Unsat(core) if core.is_empty() => {
+                println!("No solution, even without the assumptions");
+            }
+            AssumptionResult::Unsat(core) => {
+                println!("No solution, these assumptions conflict:");
+                for index in core {
+                    let (point, cell) = &options.assumptions[index];
+                    println!("    ({}, {}) {:?}", point.row, point.col, cell);
+                }
+            }
+        }
+        return Ok(());
+    }
+    if options.diagnose {
+        match csp.unsat_clue_core() {
+            Some(core) => csp.print_clue_core(&core),
+            None => println!("The puzzle has a solution"),
+        }
+        return Ok(());
+    }
+    if options.max_csp {
+        let result = csp.max_csp(&options.clue_weights)?;
+        csp.print_max_csp(&result);
+        if let Some(path) = &options.png_path {
+            csp.render_board(&csp.board_from_assignment(&result.assignment)).save_png(path)?;
+        }
+        return Ok(());
+    }
+    if options.repair {
+        let max_edits = options.max_edits.unwrap_or(2);
+        let repairs = csp.repair_suggestions(max_edits, options.max_suggestions.unwrap_or(10));
+        if repairs.is_empty() {
+            match csp.uniqueness() {
+                Uniqueness::Unique(_) => println!("The puzzle already has a unique solution"),
+                _ => println!("No repair with at most {} edits found", max_edits),
+            }
+        } else {
+            csp.print_repairs(&repairs);
+        }
+        return Ok(());
+    }
+    if let Some(objective) = &options.objective {
+        let sense = if options.minimize { optimize::Sense::Minimize } else { optimize::Sense::Maximize };
+        match csp.optimize(objective, sense, options.max_nodes)? {
+            Some(optimum) => {
+                csp.print_optimum(&optimum);
+                if let Some(path) = &options.png_path {
+                    csp.render_board(&csp.board_from_assignment(&optimum.assignment)).save_png(path)?;
+                }
+            }
+            None if options.max_nodes.is_some() => println!("No solution found within the node limit"),
+            None => println!("No solution"),
+        }
+        return Ok(());
+    }
+    if options.uniqueness {
+        match csp.uniqueness() {
+            Uniqueness::None => println!("No solution"),
+            Uniqueness::Unique(solution) => {
+                println!("Unique solution");
+                csp.print_cells(&csp.board_from_assignment(&solution));
+            }
+            Uniqueness::Multiple(first, second) => {
+                println!("Multiple solutions, two of them differ in:");
+                let diff = csp.diff(&first, &second);
+                csp.print_diff(&first, &second, &diff);
+            }
+        }
+        return Ok(());
+    }
+    if let Some(limit) = options.enumerate {
+        match run_solutions(&mut csp, limit, true, &options)? {
+            (count, None) => println!("{} solutions found", count),
+            (count, Some(stopped)) => println!("Search stopped, {}: {} solutions found so far", stopped, count),
+        }
+        return Ok(());
+    }
+    if options.trace {
+        trace_search(&mut csp);
+        return Ok(());
+    }
+    let mut limits = search_limits(&options);
+    if options.local_search {
+        // local search can't tell when a puzzle has no solution, so it always needs a limit
+        if options.timeout.is_none() && options.max_nodes.is_none() {
+            limits = limits.with_max_nodes(1_000_000);
+        }
+        let defaults = local_search::LocalSearchParams::default();
+        let params = local_search::LocalSearchParams {
+            noise: options.noise.unwrap_or(defaults.noise),
+            tabu_tenure: options.tabu_tenure.unwrap_or(defaults.tabu_tenure),
+            seed: options.seed.unwrap_or(defaults.seed),
+        };
+        let result = csp.local_search(&params, &limits);
+        csp.board = csp.board_from_assignment(&result.assignment);
+        if let Some(limit) = result.limit {
+            println!("No solution found, {}, the best board has {} violations:", limit, result.violations);
+        }
         csp.print_board();
+        if let Some(path) = &options.png_path {
+            csp.render_board(&csp.board).save_png(path)?;
+        }
+        if options.stats {
+            println!("{} flips in {:.3}s", result.stats.nodes, result.stats.elapsed.as_secs_f64());
+        }
+        return Ok(());
+    }
+    if let Some(configs) = &options.portfolio {
+        let configs = if configs.is_empty() { portfolio::PortfolioConfig::defaults() } else { configs.clone() };
+        let outcome = csp.solve_portfolio(&configs, &limits).expect("The portfolio has no configurations");
+        match &outcome.result {
+            SolveResult::Solved(solution, _) => {
+                csp.board = csp.board_from_assignment(solution);
+                csp.print_board();
+                if let Some(path) = &options.png_path {
+                    csp.render_board(&csp.board).save_png(path)?;
+                }
+            }
+            SolveResult::Unsat(_) => println!("No solution, run with --diagnose to find the conflicting clues"),
+            SolveResult::LimitReached(limit, _) => println!("Search stopped without a solution: {}", limit),
+        }
+        println!("Won by {}", outcome.winner);
+        if options.stats {
+            println!("{}", outcome.result.stats());
+        }
+        return Ok(());
+    }
+    if let Some(threads) = options.threads {
+        if options.timeout.is_some() || options.max_nodes.is_some() || options.max_backtracks.is_some() {
+            return Err("--threads can't be combined with search limits".into());
+        }
+        match csp.solve_parallel(threads) {
+            Some(solution) => {
+                csp.board = csp.board_from_assignment(&solution);
+                csp.print_board();
+                if let Some(path) = &options.png_path {
+                    csp.render_board(&csp.board).save_png(path)?;
+                }
+            }
+            None => println!("No solution, run with --diagnose to find the conflicting clues"),
+        }
+        return Ok(());
+    }
+    let result = csp.solve(&limits);
+    match &result {
+        SolveResult::Solved(..) => {
+            csp.print_board();
+            if let Some(path) = &options.png_path {
+                csp.render_board(&csp.board).save_png(path)?;
+            }
+        }
+        SolveResult::Unsat(_) => println!("No solution, run with --diagnose to find the conflicting clues"),
+        SolveResult::LimitReached(limit, _) => println!("Search stopped without a solution: {}", limit),
+    }
+    if options.stats {
+        println!("{}", result.stats());
     }
     Ok(())
 }
 
-fn init_problem(test_case_path: String) -> Result<CSP, Box<dyn Error>> {
-    let test_case_lines: Vec<String> = std::fs::read_to_string(test_case_path)?
-        .lines()
-        .map(|l| l.parse::<String>().unwrap())
-        .collect();
-
-    let board_size: Vec<usize> = test_case_lines
-        .get(0)
-        .expect("Wrong input format. First line must be the size of the board")
-        .split(" ")
-        .map(|tok| tok.parse::<usize>().unwrap())
-        .collect();
-    let row_size = board_size[0];
-    let col_size = board_size[1];
-
-    let row_pos_poles: Vec<i32> = test_case_lines
-        .get(1)
-        .expect("Wrong input format. Second line must be the number of positive poles per row")
-        .split(" ")
-        .map(|tok| tok.parse::<i32>().unwrap())
-        .collect();
-
-    let row_neg_poles: Vec<i32> = test_case_lines
-        .get(2)
-        .expect("Wrong input format. Third line must be the number of negative poles per row")
-        .split(" ")
-        .map(|tok| tok.parse::<i32>().unwrap())
-        .collect();
-
-    let col_pos_poles: Vec<i32> = test_case_lines
-        .get(3)
-        .expect("Wrong input format. Forth line must be the number of positive poles per column")
-        .split(" ")
-        .map(|tok| tok.parse::<i32>().unwrap())
-        .collect();
-
-    let col_neg_poles: Vec<i32> = test_case_lines
-        .get(4)
-        .expect("Wrong input format. Fifth line must be the number of negative poles per column")
-        .split(" ")
-        .map(|tok| tok.parse::<i32>().unwrap())
-        .collect();
-
-    let raw_board: Vec<Vec<u8>> = test_case_lines
-        .get(5..(5 + row_size) as usize)
-        .expect("Wrong input format. Not enough rows specified")
-        .iter()
-        .map(|line| {
-            line.split(" ")
-                .map(|tok| tok.parse::<u8>().unwrap())
-                .collect::<Vec<u8>>()
-        })
-        .collect::<Vec<Vec<u8>>>();
+fn parse_options(args: Vec<String>) -> Result<Options, Box<dyn Error>> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--png" => {
+                options.png_path = Some(flag_value(&mut args, "--png expects an output path")?);
+            }
+            "--puzzle-png" => {
+                options.puzzle_png_path = Some(flag_value(&mut args, "--puzzle-png expects an output path")?);
+            }
+            "--mode" => {
+                let mode = flag_value(&mut args, "--mode expects none, fc or mac")?;
+                options.inference_mode = mode.parse()?;
+            }
+            "--backjump" => {
+                options.backjumping = true;
+            }
+            "--all" => {
+                options.enumerate = Some(None);
+            }
+            "--solutions" => {
+                options.enumerate = Some(Some(parse_flag(&mut args, "--solutions expects the number of solutions")?));
+            }
+            "--count" => {
+                options.count = Some(None);
+            }
+            "--count-limit" => {
+                options.count = Some(Some(parse_flag(&mut args, "--count-limit expects the maximum number of solutions")?));
+            }
+            "--count-dp" => {
+                options.count_models = true;
+            }
+            "--cross-check" => {
+                options.cross_check = true;
+            }
+            "--backbone" => {
+                options.backbone = true;
+            }
+            "--marginals" => {
+                options.marginals = true;
+            }
+            "--exact-limit" => {
+                options.exact_limit = Some(parse_flag(&mut args, "--exact-limit expects a number of solutions")?);
+            }
+            "--samples" => {
+                let samples = parse_flag(&mut args, "--samples expects a number of samples")?;
+                if samples == 0 {
+                    return Err("--samples must be at least 1".into());
+                }
+                options.samples = Some(samples);
+            }
+            "--restarts" => {
+                let policy = flag_value(&mut args, "--restarts expects never, luby[:unit] or geometric[:first[:factor]]")?;
+                options.restart_policy = Some(policy.parse()?);
+            }
+            "--seed" => {
+                options.seed = Some(parse_flag(&mut args, "--seed expects a number")?);
+            }
+            "--assume" => {
+                let assumption = flag_value(&mut args, "--assume expects row,col,sign")?;
+                options.assumptions.push(assumptions::parse_assumption(&assumption)?);
+            }
+            "--diagnose" => {
+                options.diagnose = true;
+            }
+            "--max-csp" => {
+                options.max_csp = true;
+            }
+            "--weight" => {
+                let weight = flag_value(&mut args, "--weight expects <clue>=<weight>, for example r3+=5")?;
+                let (clue, weight) = weight.split_once('=').ok_or("--weight expects <clue>=<weight>")?;
+                let clue = clue.parse()?;
+                let weight: u32 = weight.parse().map_err(|_| "The weight must be a non-negative number")?;
+                options.clue_weights.insert(clue, weight as i64);
+            }
+            "--repair" => {
+                options.repair = true;
+            }
+            "--max-edits" => {
+                options.max_edits = Some(parse_flag(&mut args, "--max-edits expects a number of edits")?);
+            }
+            "--max-suggestions" => {
+                options.max_suggestions = Some(parse_flag(&mut args, "--max-suggestions expects a number of suggestions")?);
+            }
+            "--optimize" => {
+                let objective = flag_value(&mut args, "--optimize expects magnets, region:row,col,row,col or weights:<path>")?;
+                options.objective = Some(optimize::parse_objective(&objective)?);
+            }
+            "--minimize" => {
+                options.minimize = true;
+            }
+            "--max-nodes" => {
+                options.max_nodes = Some(parse_flag(&mut args, "--max-nodes expects a number of nodes")?);
+            }
+            "--max-backtracks" => {
+                options.max_backtracks = Some(parse_flag(&mut args, "--max-backtracks expects a number of backtracks")?);
+            }
+            "--timeout" => {
+                options.timeout = Some(parse_flag(&mut args, "--timeout expects a number of seconds")?);
+            }
+            "--trace" => {
+                options.trace = true;
+            }
+            "--checkpoint" => {
+                options.checkpoint_path = Some(flag_value(&mut args, "--checkpoint expects a path")?);
+            }
+            "--checkpoint-every" => {
+                options.checkpoint_every = Some(parse_flag(&mut args, "--checkpoint-every expects a number of seconds")?);
+            }
+            "--resume" => {
+                options.resume_path = Some(flag_value(&mut args, "--resume expects a checkpoint path")?);
+            }
+            "--dimacs" => {
+                options.dimacs_path = Some(flag_value(&mut args, "--dimacs expects an output path")?);
+            }
+            "--sat" => {
+                options.sat = true;
+            }
+            "--sat-model" => {
+                options.sat_model_path = Some(flag_value(&mut args, "--sat-model expects the path of a SAT solver's output")?);
+            }
+            "--lp" => {
+                options.lp_path = Some(flag_value(&mut args, "--lp expects an output path")?);
+            }
+            "--opb" => {
+                options.opb_path = Some(flag_value(&mut args, "--opb expects an output path")?);
+            }
+            "--lp-solution" => {
+                options.lp_solution_path = Some(flag_value(&mut args, "--lp-solution expects the path of a MIP solver's solution")?);
+            }
+            "--opb-solution" => {
+                options.opb_solution_path = Some(flag_value(&mut args, "--opb-solution expects the path of a pseudo-Boolean solver's output")?);
+            }
+            "--minizinc" => {
+                options.minizinc_prefix = Some(flag_value(&mut args, "--minizinc expects an output path prefix")?);
+            }
+            "--xcsp3" => {
+                options.xcsp3_path = Some(flag_value(&mut args, "--xcsp3 expects an output path")?);
+            }
+            "--minizinc-solution" => {
+                options.minizinc_solution_path = Some(flag_value(&mut args, "--minizinc-solution expects the path of MiniZinc's output")?);
+            }
+            "--xcsp3-solution" => {
+                options.xcsp3_solution_path = Some(flag_value(&mut args, "--xcsp3-solution expects the path of an XCSP3 solver's output")?);
+            }
+            "--prove" => {
+                options.prove_prefix = Some(flag_value(&mut args, "--prove expects an output path prefix")?);
+            }
+            "--check-proof" => {
+                options.check_proof_prefix = Some(flag_value(&mut args, "--check-proof expects the path prefix of a certificate")?);
+            }
+            "--local-search" => {
+                options.local_search = true;
+            }
+            "--noise" => {
+                options.noise = Some(parse_flag(&mut args, "--noise expects a probability")?);
+            }
+            "--tabu" => {
+                options.tabu_tenure = Some(parse_flag(&mut args, "--tabu expects a number of flips")?);
+            }
+            "--portfolio" => {
+                options.portfolio.get_or_insert_with(Vec::new);
+            }
+            "--config" => {
+                let config = flag_value(&mut args, "--config expects a configuration like mac,seed=1,restarts=luby:100")?;
+                let config = config.parse()?;
+                options.portfolio.get_or_insert_with(Vec::new).push(config);
+            }
+            "--threads" => {
+                options.threads = Some(parse_flag(&mut args, "--threads expects a number of threads")?);
+            }
+            "--stats" => {
+                options.stats = true;
+            }
+            "--unique" => {
+                options.uniqueness = true;
+            }
+            "--diff" => {
+                let left = flag_value(&mut args, "--diff expects two boards")?;
+                let right = flag_value(&mut args, "--diff expects two boards")?;
+                options.diff = Some((left, right));
+            }
+            _ if arg.starts_with("--") => {
+                return Err(format!("Unknown option '{}'", arg).into());
+            }
+            _ if !options.test_case_path.is_empty() => {
+                return Err(format!("Unexpected argument '{}', the test case path is already '{}'", arg, options.test_case_path).into());
+            }
+            _ => {
+                options.test_case_path = arg;
+            }
+        }
+    }
+    if options.test_case_path.is_empty() {
+        return Err("Please provide a test case path as command line argument.".into());
+    }
+    if options.local_search && options.max_backtracks.is_some() {
+        return Err("--max-backtracks can't be used with --local-search, which never backtracks; bound its flips with --max-nodes".into());
+    }
+    Ok(options)
+}
+
+// Takes the value that follows a flag, `expected` describes it in the error when it is missing
+fn flag_value(args: &mut impl Iterator<Item = String>, expected: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| expected.to_string())
+}
+
+fn parse_flag<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, expected: &str) -> Result<T, String> {
+    let value = flag_value(args, expected)?;
+    value.parse().map_err(|_| format!("{}, got '{}'", expected, value))
+}
+
+fn search_limits(options: &Options) -> SolveLimits {
+    let mut limits = SolveLimits::default();
+    if let Some(timeout) = options.timeout {
+        limits = limits.with_timeout(std::time::Duration::from_secs_f64(timeout));
+    }
+    if let Some(max_nodes) = options.max_nodes {
+        limits = limits.with_max_nodes(max_nodes);
+    }
+    if let Some(max_backtracks) = options.max_backtracks {
+        limits = limits.with_max_backtracks(max_backtracks);
+    }
+    limits
+}
+
+// Enumerates or counts the solutions, starting from a checkpoint if --resume is given and saving
+// one every --checkpoint-every seconds if --checkpoint is given. Returns the number of solutions
+// including the ones found before the checkpoint, and the limit that stopped the search early if
+// any. A search stopped by a limit leaves its checkpoint behind to be resumed.
+fn run_solutions(csp: &mut CSP, limit: Option<usize>, print: bool, options: &Options) -> Result<(usize, Option<Limit>), Box<dyn Error>> {
+    // the search checks the clock after this many steps
+    const STEPS_BETWEEN_CHECKS: usize = 100_000;
+    let view = csp.clone();
+    if let Some(threads) = options.threads {
+        if options.checkpoint_path.is_some() || options.resume_path.is_some() {
+            return Err("--threads can't be combined with --checkpoint or --resume".into());
+        }
+        if options.timeout.is_some() || options.max_nodes.is_some() || options.max_backtracks.is_some() {
+            return Err("--threads can't be combined with search limits".into());
+        }
+        if !print {
+            return Ok((csp.count_parallel(threads, limit), None));
+        }
+        let mut printed = 0;
+        let count = csp.enumerate_parallel(threads, limit, |assignment| {
+            printed += 1;
+            println!("Solution {}", printed);
+            view.print_cells(&view.board_from_assignment(&assignment));
+            println!();
+        });
+        return Ok((count, None));
+    }
+    let solutions = match &options.resume_path {
+        Some(path) => csp.resume_solutions(&std::fs::read_to_string(path)?, limit)?,
+        None => csp.solutions(limit),
+    };
+    let mut solutions = solutions.within(search_limits(options));
+    let interval = std::time::Duration::from_secs_f64(options.checkpoint_every.unwrap_or(60.0));
+    let mut last_checkpoint = std::time::Instant::now();
+    let stopped = loop {
+        match solutions.advance(Some(STEPS_BETWEEN_CHECKS)) {
+            Progress::Solution(assignment) => {
+                if print {
+                    println!("Solution {}", solutions.found);
+                    view.print_cells(&view.board_from_assignment(&assignment));
+                    println!();
+                }
+            }
+            Progress::Paused => {}
+            Progress::Exhausted => break None,
+            Progress::LimitReached(limit) => break Some(limit),
+        }
+        if let Some(path) = &options.checkpoint_path {
+            if last_checkpoint.elapsed() >= interval {
+                solutions.save_checkpoint(path)?;
+                last_checkpoint = std::time::Instant::now();
+            }
+        }
+    };
+    if let Some(path) = &options.checkpoint_path {
+        if stopped.is_some() {
+            solutions.save_checkpoint(path)?;
+        } else if std::path::Path::new(path).exists() {
+            // the run is complete, a checkpoint left behind would only resume an empty search
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok((solutions.found, stopped))
+}
+
+// Drives the search one step at a time until the first solution, printing every step with the
+// depth, the number of assigned magnets and the values left in the domains
+fn trace_search(csp: &mut CSP) {
+    let view = csp.clone();
+    let mut solver = csp.solver();
+    loop {
+        let step = solver.step();
+        let assigned = solver.assignment().iter().filter(|value| **value != Value::Unassigned).count();
+        let values_left: usize = solver.domains().map_or(0, |domains| domains.iter().map(|values| values.len()).sum());
+        let prefix = format!("depth {:3}, {:3} assigned, {:4} values left:", solver.depth(), assigned, values_left);
+        match step {
+            Step::Decision(var_index, value) => println!("{} magnet {} = {:?}", prefix, var_index, value),
+            Step::Conflict(var_index, value) => println!("{} magnet {} = {:?} fails", prefix, var_index, value),
+            Step::Backtrack(var_index) => println!("{} magnet {} has no value left, backtrack", prefix, var_index),
+            Step::Backjump(var_index, culprit) => println!("{} magnet {} has no value left, backjump to magnet {}", prefix, var_index, culprit),
+            Step::Solution(solution) => {
+                println!("{} solution", prefix);
+                view.print_cells(&view.board_from_assignment(&solution));
+                return;
+            }
+            Step::Exhausted => {
+                println!("No solution");
+                return;
+            }
+        }
+    }
+}
+
+// Reads a board printed by a previous run, or solves the puzzle with the given inference mode
+fn load_board(csp: &mut CSP, source: &str) -> Result<Assignment, Box<dyn Error>> {
+    if std::path::Path::new(source).exists() {
+        let text = std::fs::read_to_string(source)?;
+        return Ok(csp.parse_printed_board(&text)?);
+    }
+    csp.inference_mode = source.parse::<InferenceMode>()?;
+    csp.solve(&SolveLimits::default())
+        .solution()
+        .ok_or_else(|| format!("No solution found with inference mode {}", source).into())
+}
+
+fn init_problem(test_case_path: String, inference_mode: InferenceMode) -> Result<CSP, Box<dyn Error>> {
+    let text = std::fs::read_to_string(test_case_path)?;
+    Ok(parse_problem(&text, inference_mode)?)
+}
+
+// Reads a puzzle in the test case format: the board size, the four clue lines and the rows of the
+// board
+fn parse_problem(text: &str, inference_mode: InferenceMode) -> Result<CSP, String> {
+    let test_case_lines: Vec<String> = text.lines().map(|l| l.to_string()).collect();
+
+    // the numbers on the given line, `expected` describes the line in the error when it is missing
+    // or malformed
+    fn numbers<T: std::str::FromStr>(lines: &[String], index: usize, expected: &str) -> Result<Vec<T>, String> {
+        let line = lines.get(index).ok_or_else(|| format!("Wrong input format. {}", expected))?;
+        line.split(" ")
+            .map(|tok| tok.parse::<T>().map_err(|_| format!("Wrong input format. {}, got '{}'", expected, line)))
+            .collect()
+    }
+
+    let board_size: Vec<usize> = numbers(&test_case_lines, 0, "First line must be the size of the board")?;
+    let (row_size, col_size) = match board_size[..] {
+        [row_size, col_size] => (row_size, col_size),
+        _ => return Err("Wrong input format. First line must be the number of rows and columns".to_string()),
+    };
+
+    let row_pos_poles: Vec<i32> = numbers(&test_case_lines, 1, "Second line must be the number of positive poles per row")?;
+    let row_neg_poles: Vec<i32> = numbers(&test_case_lines, 2, "Third line must be the number of negative poles per row")?;
+    let col_pos_poles: Vec<i32> = numbers(&test_case_lines, 3, "Forth line must be the number of positive poles per column")?;
+    let col_neg_poles: Vec<i32> = numbers(&test_case_lines, 4, "Fifth line must be the number of negative poles per column")?;
+    if row_pos_poles.len() != row_size || row_neg_poles.len() != row_size {
+        return Err(format!("Wrong input format. Expected {} row clues", row_size));
+    }
+    if col_pos_poles.len() != col_size || col_neg_poles.len() != col_size {
+        return Err(format!("Wrong input format. Expected {} column clues", col_size));
+    }
+
+    let raw_board: Vec<Vec<u8>> = (5..5 + row_size)
+        .map(|index| numbers(&test_case_lines, index, "Not enough rows specified"))
+        .collect::<Result<_, _>>()?;
+    if raw_board.iter().any(|row| row.len() != col_size) {
+        return Err(format!("Wrong input format. Every row must have {} cells", col_size));
+    }
     Ok(CSP::new(
         row_size,
         col_size,
@@ -79,6 +885,6 @@ fn init_problem(test_case_path: String) -> Result<CSP, Box<dyn Error>> {
         col_pos_poles,
         col_neg_poles,
         raw_board,
-        csp::InferenceMode::MAC
+        inference_mode
     ))
 }
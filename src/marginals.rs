@@ -0,0 +1,109 @@
+use crate::csp::*;
+use crate::png::*;
+use crate::rng::Rng;
+
+#[derive(Debug, Clone)]
+pub struct Marginals {
+    // fraction of the solutions in which each cell is positive, negative or empty
+    pub positive: Vec<Vec<f64>>,
+    pub negative: Vec<Vec<f64>>,
+    pub empty: Vec<Vec<f64>>,
+    // number of solutions the fractions are computed from
+    pub solutions: usize,
+    // false if the solutions come from randomized searches instead of the enumeration
+    pub exact: bool,
+}
+
+impl CSP {
+    // Computes how often each cell is +, - or blank over the solutions. If there are at most
+    // `exact_limit` solutions they are all enumerated, otherwise the first solutions of `samples`
+    // randomized searches seeded from `seed` are tallied. Those are not uniform over the
+    // solutions (solutions the search reaches through more tie breaks come up more often), so the
+    // fractions are biased estimates. Returns None if there is no solution.
+    pub fn marginals(&mut self, exact_limit: usize, samples: usize, seed: u64) -> Option<Marginals> {
+        let enumerated: Vec<Assignment> = self.solutions(Some(exact_limit + 1)).collect();
+        if enumerated.is_empty() {
+            return None;
+        }
+        if enumerated.len() <= exact_limit {
+            return Some(self.tally(&enumerated, true));
+        }
+
+        let mut rng = Rng::new(seed);
+        let mut sampled = Vec::with_capacity(samples);
+        for _ in 0..samples {
+            let sample_seed = rng.next_u64();
+            if let Some(solution) = self.solutions(Some(1)).randomized(sample_seed).next() {
+                sampled.push(solution);
+            }
+        }
+        Some(self.tally(&sampled, false))
+    }
+
+    fn tally(&self, solutions: &[Assignment], exact: bool) -> Marginals {
+        let mut positive = vec![vec![0.0; self.col_size]; self.row_size];
+        let mut negative = vec![vec![0.0; self.col_size]; self.row_size];
+        let mut empty = vec![vec![0.0; self.col_size]; self.row_size];
+        let weight = 1.0 / solutions.len() as f64;
+        for solution in solutions {
+            let board = self.board_from_assignment(solution);
+            for i in 0..self.row_size {
+                for j in 0..self.col_size {
+                    match board[i][j] {
+                        BoardCell::Positive => positive[i][j] += weight,
+                        BoardCell::Negative => negative[i][j] += weight,
+                        _ => empty[i][j] += weight,
+                    }
+                }
+            }
+        }
+        Marginals {
+            positive,
+            negative,
+            empty,
+            solutions: solutions.len(),
+            exact,
+        }
+    }
+
+    pub fn print_marginals(&self, marginals: &Marginals) {
+        if marginals.exact {
+            println!("Exact over all {} solutions", marginals.solutions);
+        } else {
+            println!(
+                "Biased estimate from the first solutions of {} randomized searches, not uniform samples",
+                marginals.solutions
+            );
+        }
+        println!("{:>4} {:>4} {:>6} {:>6} {:>6}", "row", "col", "+", "-", "blank");
+        for i in 0..self.row_size {
+            for j in 0..self.col_size {
+                println!(
+                    "{:4} {:4} {:6.3} {:6.3} {:6.3}",
+                    i, j, marginals.positive[i][j], marginals.negative[i][j], marginals.empty[i][j]
+                );
+            }
+        }
+    }
+
+    // Colors every cell by mixing red for +, blue for - and white for blank with the weights of
+    // the marginals
+    pub fn render_marginals(&self, marginals: &Marginals) -> Canvas {
+        const POSITIVE: [f64; 3] = [215.0, 40.0, 40.0];
+        const NEGATIVE: [f64; 3] = [40.0, 80.0, 215.0];
+        const EMPTY: [f64; 3] = [255.0, 255.0, 255.0];
+        let heat = |i: usize, j: usize| {
+            let mut color = [0u8; 3];
+            for (channel, value) in color.iter_mut().enumerate() {
+                *value = (marginals.positive[i][j] * POSITIVE[channel]
+                    + marginals.negative[i][j] * NEGATIVE[channel]
+                    + marginals.empty[i][j] * EMPTY[channel])
+                    .round()
+                    .clamp(0.0, 255.0) as u8;
+            }
+            Some(color)
+        };
+        let blank = vec![vec![BoardCell::Unassigned; self.col_size]; self.row_size];
+        self.render_board_with(&blank, &heat)
+    }
+}
@@ -0,0 +1,209 @@
+use crate::clues::Clue;
+use crate::csp::*;
+use crate::solver::{Solver, Step};
+use std::collections::HashMap;
+
+// Weight of every clue when it is treated as a soft constraint, clues that are not listed weigh 1.
+// Weights must not be negative.
+pub type ClueWeights = HashMap<Clue, i64>;
+
+#[derive(Debug, Clone)]
+pub struct MaxCspResult {
+    pub assignment: Assignment,
+    // total weighted deviation from the clues
+    pub cost: i64,
+    // clues the assignment misses, with the number of poles actually in the line
+    pub violated: Vec<(Clue, i32)>,
+}
+
+// State of the branch and bound search, which drives the stack solver with the clues of the CSP
+// cleared, so the search itself only enforces the like pole rule
+struct BranchAndBound {
+    // every clue with its value and weight
+    clues: Vec<(Clue, i32, i64)>,
+    // for every clue, the magnets and values that add a pole to its line
+    adders: Vec<Vec<(VariableIndex, Value)>>,
+    // incumbent: the best complete assignment found so far and its cost
+    best: (Assignment, i64),
+    // known lower bound on the optimal cost, the search stops once the incumbent reaches it
+    floor: i64,
+}
+
+impl CSP {
+    // Finds the assignment that minimizes the total weighted deviation from the row and column
+    // clues while never placing like poles next to each other. Useful for puzzles with no
+    // solution, for solvable puzzles the cost is 0. Fails if a weight is given to a clue that is
+    // not on the board or is unknown.
+    pub fn max_csp(&mut self, weights: &ClueWeights) -> Result<MaxCspResult, String> {
+        for clue in weights.keys() {
+            if !self.has_clue(*clue) {
+                return Err(format!("The weighted clue {} is outside the {}x{} board", clue, self.row_size, self.col_size));
+            }
+            if self.clue_value(*clue) < 0 {
+                return Err(format!("The weighted clue {} is unknown, only known clues can be weighted", clue));
+            }
+        }
+        // a solution breaks no clue. Proving that none exists is much cheaper with the row sweep
+        // counter than with branch and bound, and then at least one clue must be broken
+        if self.is_satisfiable() {
+            if let Some(assignment) = self.solutions(Some(1)).next() {
+                return Ok(MaxCspResult {
+                    assignment,
+                    cost: 0,
+                    violated: Vec::new(),
+                });
+            }
+        }
+
+        self.reset();
+        let weighted: Vec<(Clue, i64)> = self
+            .clues()
+            .into_iter()
+            .map(|clue| (clue, *weights.get(&clue).unwrap_or(&1)))
+            .collect();
+        let floor = weighted.iter().map(|(_, weight)| *weight).min().unwrap_or(0);
+        let best = self.initial_incumbent(&weighted);
+        let clues: Vec<(Clue, i32, i64)> = weighted.into_iter().map(|(clue, weight)| (clue, self.clue_value(clue), weight)).collect();
+        let adders = clues.iter().map(|(clue, _, _)| self.adders(*clue)).collect();
+
+        let backjumping = self.backjumping;
+        self.backjumping = false;
+        for (clue, _, _) in &clues {
+            self.set_clue_value(*clue, -1);
+        }
+        let mut search = BranchAndBound {
+            clues,
+            adders,
+            best,
+            floor,
+        };
+        search.run(&mut self.solver());
+        for (clue, value, _) in &search.clues {
+            self.set_clue_value(*clue, *value);
+        }
+        self.backjumping = backjumping;
+        let (assignment, cost) = search.best;
+        self.reset();
+
+        let board = self.board_from_assignment(&assignment);
+        let violated = self
+            .clues()
+            .into_iter()
+            .map(|clue| (clue, self.clue_count(clue, &board)))
+            .filter(|(clue, count)| *count != self.clue_value(*clue))
+            .collect();
+        Ok(MaxCspResult {
+            assignment,
+            cost,
+            violated,
+        })
+    }
+
+    // The empty board breaks no hard constraint, so it is always an incumbent. Usually dropping a
+    // single clue already makes the puzzle solvable, and the cheapest such solution is a far
+    // better starting point.
+    fn initial_incumbent(&mut self, clues: &[(Clue, i64)]) -> (Assignment, i64) {
+        let cost_of = |csp: &CSP, assignment: &Assignment| -> i64 {
+            let board = csp.board_from_assignment(assignment);
+            clues
+                .iter()
+                .map(|(clue, weight)| weight * (csp.clue_count(*clue, &board) - csp.clue_value(*clue)).abs() as i64)
+                .sum()
+        };
+        let empty: Assignment = vec![Value::Empty; self.variables.len()];
+        let mut best = (empty.clone(), cost_of(self, &empty));
+        for (clue, _) in clues {
+            let value = self.clue_value(*clue);
+            self.set_clue_value(*clue, -1);
+            let solution = if self.is_satisfiable() { self.solutions(Some(1)).next() } else { None };
+            self.set_clue_value(*clue, value);
+            if let Some(assignment) = solution {
+                let cost = cost_of(self, &assignment);
+                if cost < best.1 {
+                    best = (assignment, cost);
+                }
+            }
+        }
+        self.reset();
+        best
+    }
+
+    // Magnets and values that put a pole of the clue's sign in its line. A magnet lying along the
+    // line does so with both values, the two entries are next to each other.
+    fn adders(&self, clue: Clue) -> Vec<(VariableIndex, Value)> {
+        let (cells, sign) = self.clue_cells(clue);
+        let mut adders = Vec::new();
+        for (var_index, variable) in self.variables.iter().enumerate() {
+            for value in [Value::Pole1PositivePole2Negative, Value::Pole2PositivePole1Negative] {
+                let positive = if value == Value::Pole1PositivePole2Negative { 0 } else { 1 };
+                let pole = if sign == BoardCell::Positive { &variable.poles[positive] } else { &variable.poles[1 - positive] };
+                if cells.contains(pole) {
+                    adders.push((var_index, value));
+                }
+            }
+        }
+        adders
+    }
+
+    pub fn print_max_csp(&self, result: &MaxCspResult) {
+        self.print_cells(&self.board_from_assignment(&result.assignment));
+        println!();
+        println!("Total weighted deviation: {}", result.cost);
+        for (clue, count) in &result.violated {
+            let expected = self.clue_value(*clue);
+            println!("    {} = {}, the board has {} (off by {})", clue, expected, count, (count - expected).abs());
+        }
+    }
+}
+
+impl BranchAndBound {
+    // Every decision whose bound is no better than the incumbent is cut off right away
+    fn run(&mut self, solver: &mut Solver) {
+        while self.best.1 > self.floor {
+            match solver.step() {
+                Step::Decision(_, _) => {
+                    let bound = solver.domains().map_or(0, |domains| self.lower_bound(solver.csp, domains, solver.assignment()));
+                    if bound >= self.best.1 {
+                        solver.cut();
+                    }
+                }
+                Step::Solution(assignment) => {
+                    // every line is closed, so the bound is the exact cost
+                    let cost = self.lower_bound(solver.csp, &Vec::new(), &assignment);
+                    if cost < self.best.1 {
+                        self.best = (assignment, cost);
+                    }
+                }
+                Step::Exhausted => return,
+                _ => {}
+            }
+        }
+    }
+
+    // Counts only grow, so a line already above its clue keeps that deviation. A magnet adds at
+    // most one pole of each sign to a line, so a line misses its clue at least by what is left
+    // after every unassigned magnet that still has a value adding the sign there gets it.
+    fn lower_bound(&self, csp: &CSP, domains: &Domain, assignment: &Assignment) -> i64 {
+        let mut bound = 0;
+        for ((clue, target, weight), adders) in self.clues.iter().zip(&self.adders) {
+            let count = csp.current_count(*clue);
+            if count > *target {
+                bound += weight * (count - target) as i64;
+                continue;
+            }
+            let mut missing = target - count;
+            let mut counted = None;
+            for (var_index, value) in adders {
+                if missing == 0 {
+                    break;
+                }
+                if counted != Some(*var_index) && assignment[*var_index] == Value::Unassigned && domains[*var_index].contains(value) {
+                    missing -= 1;
+                    counted = Some(*var_index);
+                }
+            }
+            bound += weight * missing as i64;
+        }
+        bound
+    }
+}
@@ -0,0 +1,294 @@
+use crate::clues::Clue;
+use crate::csp::*;
+
+// A linear constraint over the 0/1 model variables, variable 2i is magnet i with pole 1 positive
+// and 2i + 1 magnet i with pole 2 positive, a magnet with neither is empty
+#[derive(Debug, Clone)]
+pub struct LinearConstraint {
+    pub name: String,
+    pub terms: Vec<(i64, usize)>,
+    pub relation: Relation,
+    pub rhs: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    AtMost,
+    Equal,
+}
+
+// Terms per line in the LP file, some readers limit the line length
+const TERMS_PER_LINE: usize = 10;
+
+impl CSP {
+    // Model variable that is 1 when the cell holds a pole of the sign
+    pub fn cell_model_variable(&self, cell: &Point, sign: &BoardCell) -> usize {
+        let var_index = self.board_variable_association[cell.row][cell.col];
+        let first_pole = self.variables[var_index].poles[0] == *cell;
+        match (first_pole, sign) {
+            (true, BoardCell::Positive) | (false, BoardCell::Negative) => 2 * var_index,
+            _ => 2 * var_index + 1,
+        }
+    }
+
+    // A magnet has at most one orientation, every known clue is a count equality over its line and
+    // touching cells of different magnets hold at most one pole of each sign
+    pub fn linear_model(&self) -> Vec<LinearConstraint> {
+        let mut constraints = Vec::new();
+        for var_index in 0..self.variables.len() {
+            constraints.push(LinearConstraint {
+                name: format!("magnet_{}", var_index),
+                terms: vec![(1, 2 * var_index), (1, 2 * var_index + 1)],
+                relation: Relation::AtMost,
+                rhs: 1,
+            });
+        }
+        for clue in self.clues() {
+            let (cells, sign) = self.clue_cells(clue);
+            let kind = match clue {
+                Clue::RowPositive(i) => format!("row_{}_pos", i),
+                Clue::RowNegative(i) => format!("row_{}_neg", i),
+                Clue::ColPositive(j) => format!("col_{}_pos", j),
+                Clue::ColNegative(j) => format!("col_{}_neg", j),
+            };
+            constraints.push(LinearConstraint {
+                name: kind,
+                terms: cells.iter().map(|cell| (1, self.cell_model_variable(cell, &sign))).collect(),
+                relation: Relation::Equal,
+                rhs: self.clue_value(clue) as i64,
+            });
+        }
+        for row in 0..self.row_size {
+            for col in 0..self.col_size {
+                let cell = Point { row, col };
+                for other in [Point { row, col: col + 1 }, Point { row: row + 1, col }] {
+                    if other.row >= self.row_size || other.col >= self.col_size {
+                        continue;
+                    }
+                    if self.board_variable_association[row][col] == self.board_variable_association[other.row][other.col] {
+                        continue;
+                    }
+                    for (sign, name) in [(BoardCell::Positive, "pos"), (BoardCell::Negative, "neg")] {
+                        constraints.push(LinearConstraint {
+                            name: format!("adj_{}_{}_{}_{}_{}", name, row, col, other.row, other.col),
+                            terms: vec![(1, self.cell_model_variable(&cell, &sign)), (1, self.cell_model_variable(&other, &sign))],
+                            relation: Relation::AtMost,
+                            rhs: 1,
+                        });
+                    }
+                }
+            }
+        }
+        constraints
+    }
+
+    // CPLEX LP format, a feasibility problem with a zero objective
+    pub fn to_lp(&self) -> String {
+        let mut text = format!("\\ ravenous-polarity {}x{} board with {} magnets\n", self.row_size, self.col_size, self.variables.len());
+        text += "\\ m<i>p: magnet i (from 0) has pole 1 positive, m<i>n: pole 2 positive, neither: empty\n";
+        text += "Minimize\n obj:";
+        if !self.variables.is_empty() {
+            text += &format!(" 0 {}", lp_name(0));
+        }
+        text += "\nSubject To\n";
+        for constraint in self.linear_model() {
+            text += &format!(" {}:", constraint.name);
+            for (position, (coefficient, variable)) in constraint.terms.iter().enumerate() {
+                if position > 0 && position % TERMS_PER_LINE == 0 {
+                    text += "\n   ";
+                }
+                let sign = match (*coefficient < 0, position > 0) {
+                    (true, true) => "- ",
+                    (true, false) => "-",
+                    (false, true) => "+ ",
+                    (false, false) => "",
+                };
+                text += &format!(" {}{} {}", sign, coefficient.abs(), lp_name(*variable));
+            }
+            let relation = match constraint.relation {
+                Relation::AtMost => "<=",
+                Relation::Equal => "=",
+            };
+            text += &format!(" {} {}\n", relation, constraint.rhs);
+        }
+        text += "Binary\n";
+        for variable in 0..2 * self.variables.len() {
+            text += &format!(" {}\n", lp_name(variable));
+        }
+        text += "End\n";
+        text
+    }
+
+    // OPB pseudo-Boolean format without an objective, variable x<k> is model variable k - 1 and
+    // at-most constraints are written as at-least constraints with negated coefficients
+    pub fn to_opb(&self) -> String {
+        let constraints = self.linear_model();
+        let mut text = format!("* #variable= {} #constraint= {}\n", 2 * self.variables.len(), constraints.len());
+        text += &format!("* ravenous-polarity {}x{} board with {} magnets\n", self.row_size, self.col_size, self.variables.len());
+        text += "* x<2i+1>: magnet i (from 0) has pole 1 positive, x<2i+2>: pole 2 positive, neither: empty\n";
+        for constraint in constraints {
+            let negate = if constraint.relation == Relation::AtMost { -1 } else { 1 };
+            for (coefficient, variable) in &constraint.terms {
+                text += &format!("{:+} x{} ", coefficient * negate, variable + 1);
+            }
+            let relation = if constraint.relation == Relation::AtMost { ">=" } else { "=" };
+            text += &format!("{} {} ;\n", relation, constraint.rhs * negate);
+        }
+        text
+    }
+
+    // Reads the solution file of a MIP solver for the LP file: name and value pairs as written by
+    // Gurobi, CBC, SCIP or HiGHS, or the variable elements of a CPLEX solution. Variables that
+    // are not listed are 0. Returns None when the status line of the solver reports the model
+    // infeasible.
+    pub fn decode_lp_solution(&self, text: &str) -> Result<Option<Assignment>, String> {
+        if text.lines().any(is_infeasible_status) {
+            return Ok(None);
+        }
+        let mut values = vec![false; 2 * self.variables.len()];
+        for line in text.lines() {
+            // <variable name="m0p" index="0" value="1"/>
+            if let (Some(name), Some(value)) = (xml_attribute(line, "name"), xml_attribute(line, "value")) {
+                self.set_lp_value(&mut values, name, value)?;
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            for (position, token) in tokens.iter().enumerate() {
+                if parse_lp_name(token, self.variables.len()).is_some() {
+                    if let Some(value) = tokens.get(position + 1) {
+                        self.set_lp_value(&mut values, token, value)?;
+                    }
+                    break;
+                }
+            }
+        }
+        self.assignment_from_model_values(&values).map(Some)
+    }
+
+    // Reads the output of a pseudo-Boolean solver for the OPB file: an s line and v lines with
+    // x<k> for 1 and -x<k> for 0. Returns None when the solver reports it unsatisfiable.
+    pub fn decode_opb_solution(&self, text: &str) -> Result<Option<Assignment>, String> {
+        let mut values = vec![false; 2 * self.variables.len()];
+        for line in text.lines() {
+            let line = line.trim();
+            if line.starts_with('s') && line.contains("UNSATISFIABLE") {
+                return Ok(None);
+            }
+            let literals = match line.strip_prefix('v') {
+                Some(literals) => literals,
+                None => continue,
+            };
+            for literal in literals.split_whitespace() {
+                let (value, name) = match literal.strip_prefix('-') {
+                    Some(name) => (false, name),
+                    None => (true, literal),
+                };
+                let variable = name
+                    .strip_prefix('x')
+                    .and_then(|number| number.parse::<usize>().ok())
+                    .filter(|number| *number >= 1 && *number <= values.len())
+                    .ok_or_else(|| format!("Unknown variable '{}' in the solution", literal))?;
+                values[variable - 1] = value;
+            }
+        }
+        self.assignment_from_model_values(&values).map(Some)
+    }
+
+    fn set_lp_value(&self, values: &mut [bool], name: &str, value: &str) -> Result<(), String> {
+        let variable = parse_lp_name(name, self.variables.len()).ok_or_else(|| format!("Unknown variable '{}' in the solution", name))?;
+        let value: f64 = value.parse().map_err(|_| format!("Bad value '{}' for {} in the solution", value, name))?;
+        values[variable] = value > 0.5;
+        Ok(())
+    }
+
+    fn assignment_from_model_values(&self, values: &[bool]) -> Result<Assignment, String> {
+        (0..self.variables.len())
+            .map(|var_index| match (values[2 * var_index], values[2 * var_index + 1]) {
+                (true, false) => Ok(Value::Pole1PositivePole2Negative),
+                (false, true) => Ok(Value::Pole2PositivePole1Negative),
+                (false, false) => Ok(Value::Empty),
+                (true, true) => Err(format!("The solution places magnet {} in both orientations", var_index)),
+            })
+            .collect()
+    }
+}
+
+fn lp_name(variable: usize) -> String {
+    format!("m{}{}", variable / 2, if variable.is_multiple_of(2) { 'p' } else { 'n' })
+}
+
+fn parse_lp_name(name: &str, magnets: usize) -> Option<usize> {
+    let body = name.strip_prefix('m')?;
+    let (number, orientation) = body.split_at(body.len().checked_sub(1)?);
+    let var_index: usize = number.parse().ok()?;
+    if var_index >= magnets {
+        return None;
+    }
+    match orientation {
+        "p" => Some(2 * var_index),
+        "n" => Some(2 * var_index + 1),
+        _ => None,
+    }
+}
+
+// Status lines of an infeasible model: CBC's "Infeasible - objective value 0" (or "Integer
+// infeasible"), HiGHS's "Infeasible" under "Model status", SCIP's "solution status: infeasible"
+// and the solutionStatusString of a CPLEX header. Log lines such as "primal infeasibility 0" are
+// not statuses.
+fn is_infeasible_status(line: &str) -> bool {
+    let line = line.trim().to_lowercase();
+    let status = match line.strip_prefix("solution status:") {
+        Some(status) => status.trim(),
+        None => xml_attribute(&line, "solutionstatusstring").unwrap_or(&line),
+    };
+    let status = status.strip_prefix("integer ").unwrap_or(status);
+    status == "infeasible" || status.starts_with("infeasible ")
+}
+
+fn xml_attribute<'a>(line: &'a str, attribute: &str) -> Option<&'a str> {
+    let start = line.find(&format!(" {}=\"", attribute))? + attribute.len() + 3;
+    let length = line[start..].find('"')?;
+    Some(&line[start..start + length])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_puzzles::*;
+
+    // CBC style solution file with the variables that are 1
+    fn cbc_solution(status: &str, assignment: &Assignment) -> String {
+        let mut text = format!("{}\n", status);
+        for (var_index, value) in assignment.iter().enumerate() {
+            let variable = match value {
+                Value::Pole1PositivePole2Negative => 2 * var_index,
+                Value::Pole2PositivePole1Negative => 2 * var_index + 1,
+                _ => continue,
+            };
+            text += &format!("{:6} {:20} 1 0\n", variable, lp_name(variable));
+        }
+        text
+    }
+
+    #[test]
+    fn decodes_a_solution_whose_log_mentions_infeasibility() {
+        let mut csp = puzzle(TEST_CASE_1, InferenceMode::MAC);
+        let solution = csp.solutions(Some(1)).next().unwrap();
+        let text = cbc_solution("Optimal - objective value 0.00000000\nprimal infeasibility 0, 0 infeasible rows", &solution);
+        assert_eq!(csp.decode_lp_solution(&text), Ok(Some(solution)));
+    }
+
+    #[test]
+    fn reads_the_infeasible_status_lines() {
+        let csp = puzzle(TEST_CASE_1, InferenceMode::MAC);
+        for text in [
+            "Infeasible - objective value 0",
+            "Integer infeasible - objective value 0.00000000",
+            "Model status\nInfeasible\n",
+            "solution status: infeasible",
+            "<header problemName=\"t1.lp\" solutionStatusValue=\"103\" solutionStatusString=\"integer infeasible\"/>",
+        ] {
+            assert_eq!(csp.decode_lp_solution(text), Ok(None), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use crate::bigint::BigUint;
+use crate::csp::*;
+use std::collections::HashMap;
+
+// Where a cell sits inside its magnet slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CellRole {
+    HorizontalPole1,
+    HorizontalPole2,
+    VerticalPole1,
+    VerticalPole2,
+    Uncovered,
+}
+
+// Everything the rows below need to know about the rows above. The vertical magnets still open
+// are the VerticalPole1 cells of the previous row, their second pole is forced to the opposite
+// sign so the previous row profile covers them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SweepState {
+    // sign of every cell of the previous row: 1, -1 or 0
+    previous_row: Vec<i8>,
+    // poles counted so far per column, left at 0 for columns whose clue is unknown
+    col_pos_poles: Vec<i32>,
+    col_neg_poles: Vec<i32>,
+}
+
+struct RowSweep<'a> {
+    csp: &'a CSP,
+    roles: Vec<Vec<CellRole>>,
+}
+
+// A state of the rows above and its number of completions, being extended by one more row
+struct RowEntry<'a> {
+    row: usize,
+    state: &'a SweepState,
+    count: &'a BigUint,
+}
+
+impl CSP {
+    // Counts the solutions exactly with a dynamic program that sweeps the board row by row.
+    // It is independent of the backtracking search and uses the same rules: like poles can't be
+    // orthogonal neighbors and every known clue must be met exactly.
+    pub fn count_models(&self) -> BigUint {
+        let mut roles = vec![vec![CellRole::Uncovered; self.col_size]; self.row_size];
+        for variable in &self.variables {
+            let (pole1, pole2) = (&variable.poles[0], &variable.poles[1]);
+            if pole1.row == pole2.row {
+                roles[pole1.row][pole1.col] = CellRole::HorizontalPole1;
+                roles[pole2.row][pole2.col] = CellRole::HorizontalPole2;
+            } else {
+                roles[pole1.row][pole1.col] = CellRole::VerticalPole1;
+                roles[pole2.row][pole2.col] = CellRole::VerticalPole2;
+            }
+        }
+        let sweep = RowSweep { csp: self, roles };
+
+        let mut states: HashMap<SweepState, BigUint> = HashMap::new();
+        states.insert(
+            SweepState {
+                previous_row: vec![0; self.col_size],
+                col_pos_poles: vec![0; self.col_size],
+                col_neg_poles: vec![0; self.col_size],
+            },
+            BigUint::from(1),
+        );
+        for row in 0..self.row_size {
+            let mut next_states: HashMap<SweepState, BigUint> = HashMap::new();
+            for (state, count) in &states {
+                let entry = RowEntry { row, state, count };
+                let mut next = state.clone();
+                let mut current_row = vec![0; self.col_size];
+                sweep.extend_row(&entry, 0, &mut current_row, (0, 0), &mut next, &mut next_states);
+            }
+            states = next_states;
+        }
+
+        let mut total = BigUint::zero();
+        for (state, count) in &states {
+            let columns_met = (0..self.col_size).all(|j| {
+                !CSP::misses_clue(state.col_pos_poles[j], self.col_pos_poles[j])
+                    && !CSP::misses_clue(state.col_neg_poles[j], self.col_neg_poles[j])
+            });
+            if columns_met {
+                total.add_assign(count);
+            }
+        }
+        total
+    }
+}
+
+impl RowSweep<'_> {
+    // Enumerates every way to fill the rest of the row starting at column `col` and adds the
+    // resulting states to `next_states`. `next` holds the column counts including this row.
+    fn extend_row(
+        &self,
+        entry: &RowEntry,
+        col: usize,
+        current_row: &mut Vec<i8>,
+        row_poles: (i32, i32),
+        next: &mut SweepState,
+        next_states: &mut HashMap<SweepState, BigUint>,
+    ) {
+        let csp = self.csp;
+        let (row, state) = (entry.row, entry.state);
+        if col == csp.col_size {
+            self.finish_row(entry, current_row, row_poles, next, next_states);
+            return;
+        }
+
+        let signs: Vec<(i8, i8)> = match self.roles[row][col] {
+            CellRole::HorizontalPole1 => vec![(1, -1), (-1, 1), (0, 0)],
+            CellRole::VerticalPole1 => vec![(1, 0), (-1, 0), (0, 0)],
+            CellRole::VerticalPole2 => vec![(-state.previous_row[col], 0)],
+            CellRole::Uncovered => vec![(0, 0)],
+            // always filled together with its first pole
+            CellRole::HorizontalPole2 => unreachable!(),
+        };
+        let width = if self.roles[row][col] == CellRole::HorizontalPole1 { 2 } else { 1 };
+
+        for (first, second) in signs {
+            let cells = &[first, second][..width];
+            let mut row_pos = row_poles.0;
+            let mut row_neg = row_poles.1;
+            let mut feasible = true;
+            for (offset, sign) in cells.iter().enumerate() {
+                let j = col + offset;
+                if *sign != 0 {
+                    let left = if j > 0 && offset == 0 { current_row[j - 1] } else { 0 };
+                    if left == *sign || state.previous_row[j] == *sign {
+                        feasible = false;
+                    }
+                }
+                current_row[j] = *sign;
+                if *sign == 1 {
+                    row_pos += 1;
+                } else if *sign == -1 {
+                    row_neg += 1;
+                }
+            }
+            if !feasible
+                || CSP::exceeds_clue(row_pos, csp.row_pos_poles[row])
+                || CSP::exceeds_clue(row_neg, csp.row_neg_poles[row])
+            {
+                continue;
+            }
+
+            let saved: Vec<(i32, i32)> = (col..col + width)
+                .map(|j| (next.col_pos_poles[j], next.col_neg_poles[j]))
+                .collect();
+            for (j, sign) in cells.iter().enumerate().map(|(offset, sign)| (col + offset, *sign)) {
+                if sign == 1 && csp.col_pos_poles[j] >= 0 {
+                    next.col_pos_poles[j] += 1;
+                    feasible &= !CSP::exceeds_clue(next.col_pos_poles[j], csp.col_pos_poles[j]);
+                } else if sign == -1 && csp.col_neg_poles[j] >= 0 {
+                    next.col_neg_poles[j] += 1;
+                    feasible &= !CSP::exceeds_clue(next.col_neg_poles[j], csp.col_neg_poles[j]);
+                }
+            }
+            if feasible {
+                self.extend_row(entry, col + width, current_row, (row_pos, row_neg), next, next_states);
+            }
+            for (offset, (pos, neg)) in saved.into_iter().enumerate() {
+                next.col_pos_poles[col + offset] = pos;
+                next.col_neg_poles[col + offset] = neg;
+            }
+        }
+    }
+
+    fn finish_row(
+        &self,
+        entry: &RowEntry,
+        current_row: &[i8],
+        row_poles: (i32, i32),
+        next: &SweepState,
+        next_states: &mut HashMap<SweepState, BigUint>,
+    ) {
+        let csp = self.csp;
+        let row = entry.row;
+        if CSP::misses_clue(row_poles.0, csp.row_pos_poles[row]) || CSP::misses_clue(row_poles.1, csp.row_neg_poles[row]) {
+            return;
+        }
+        // each remaining row can add at most one pole of each sign to a column
+        let rows_left = (csp.row_size - row - 1) as i32;
+        for j in 0..csp.col_size {
+            if (csp.col_pos_poles[j] >= 0 && next.col_pos_poles[j] + rows_left < csp.col_pos_poles[j])
+                || (csp.col_neg_poles[j] >= 0 && next.col_neg_poles[j] + rows_left < csp.col_neg_poles[j])
+            {
+                return;
+            }
+        }
+        let state = SweepState {
+            previous_row: current_row.to_vec(),
+            col_pos_poles: next.col_pos_poles.clone(),
+            col_neg_poles: next.col_neg_poles.clone(),
+        };
+        next_states.entry(state).or_default().add_assign(entry.count);
+    }
+}
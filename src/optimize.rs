@@ -0,0 +1,221 @@
+use crate::clues::Clue;
+use crate::csp::*;
+use crate::solver::{Solver, Step};
+
+// Quantity to optimize over the solutions of a puzzle
+#[derive(Debug, Clone)]
+pub enum Objective {
+    // magnets that are not left blank
+    NonEmptyMagnets,
+    // positive cells in the rectangle between the two corners, both included
+    PositiveCellsInRegion(Point, Point),
+    // sum of the weights of the cells holding a pole of either sign, indexed [row][col]
+    CellWeights(Vec<Vec<i64>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sense {
+    Maximize,
+    Minimize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Optimum {
+    pub assignment: Assignment,
+    pub value: i64,
+    // true when the search ran to the end, so no solution is better
+    pub proven_optimal: bool,
+    pub nodes: usize,
+}
+
+// Depth first branch and bound on top of the stack solver, scores are always maximized and a
+// minimization negates them
+struct OptimizationSearch<'a> {
+    objective: &'a Objective,
+    sign: i64,
+    best: Option<(Assignment, i64)>,
+    max_nodes: Option<usize>,
+    stopped: bool,
+}
+
+impl CSP {
+    // Finds the solution with the best objective value. The search stops early after `max_nodes`
+    // assignments if given, the best solution found so far is then returned without the proof of
+    // optimality. Returns None if no solution was found.
+    pub fn optimize(&mut self, objective: &Objective, sense: Sense, max_nodes: Option<usize>) -> Result<Option<Optimum>, String> {
+        if let Objective::CellWeights(weights) = objective {
+            if weights.len() != self.row_size || weights.iter().any(|row| row.len() != self.col_size) {
+                return Err(format!("The weights must have {} rows of {} numbers", self.row_size, self.col_size));
+            }
+        }
+        let mut search = OptimizationSearch {
+            objective,
+            sign: if sense == Sense::Maximize { 1 } else { -1 },
+            best: None,
+            max_nodes,
+            stopped: false,
+        };
+        // cutting a decision off blames no variable, so conflict sets would be wrong
+        let backjumping = self.backjumping;
+        self.backjumping = false;
+        let mut solver = self.solver();
+        search.run(&mut solver);
+        let nodes = solver.nodes;
+        self.backjumping = backjumping;
+        self.reset();
+        Ok(search.best.map(|(assignment, score)| Optimum {
+            assignment,
+            value: search.sign * score,
+            proven_optimal: !search.stopped,
+            nodes,
+        }))
+    }
+
+    // Objective value of a variable taking a value, the objective of a board is the sum over its
+    // magnets
+    pub fn objective_contribution(&self, objective: &Objective, var_index: VariableIndex, value: Value) -> i64 {
+        if value == Value::Empty || value == Value::Unassigned {
+            return 0;
+        }
+        let poles = &self.variables[var_index].poles;
+        match objective {
+            Objective::NonEmptyMagnets => 1,
+            Objective::PositiveCellsInRegion(from, to) => {
+                let positive = match value {
+                    Value::Pole1PositivePole2Negative => &poles[0],
+                    _ => &poles[1],
+                };
+                let inside = (from.row..=to.row).contains(&positive.row) && (from.col..=to.col).contains(&positive.col);
+                inside as i64
+            }
+            Objective::CellWeights(weights) => poles.iter().map(|pole| weights[pole.row][pole.col]).sum(),
+        }
+    }
+
+    pub fn print_optimum(&self, optimum: &Optimum) {
+        self.print_cells(&self.board_from_assignment(&optimum.assignment));
+        println!();
+        println!("Objective value: {}", optimum.value);
+        if optimum.proven_optimal {
+            println!("Proven optimal after {} nodes", optimum.nodes);
+        } else {
+            println!("Best found in {} nodes, not proven optimal", optimum.nodes);
+        }
+    }
+}
+
+impl OptimizationSearch<'_> {
+    // Every decision whose bound is no better than the incumbent is cut off right away
+    fn run(&mut self, solver: &mut Solver) {
+        solver.start();
+        self.order_values(solver);
+        loop {
+            let step = solver.step();
+            if self.max_nodes.is_some_and(|max_nodes| solver.nodes > max_nodes) {
+                self.stopped = true;
+                return;
+            }
+            match step {
+                Step::Decision(_, _) => {
+                    let bound = solver.domains().map_or(0, |domains| self.upper_bound(solver.csp, domains, solver.assignment()));
+                    if self.best.as_ref().is_some_and(|(_, best)| bound <= *best) {
+                        solver.cut();
+                    } else {
+                        self.order_values(solver);
+                    }
+                }
+                Step::Solution(assignment) => {
+                    let score = self.score(solver.csp, &assignment);
+                    if self.best.as_ref().is_none_or(|(_, best)| score > *best) {
+                        self.best = Some((assignment, score));
+                    }
+                }
+                Step::Exhausted => return,
+                _ => {}
+            }
+        }
+    }
+
+    // most promising values first so a good incumbent is found early, LCV order breaks the ties
+    fn order_values(&self, solver: &mut Solver) {
+        if let Some(frame) = solver.stack.last_mut() {
+            let var_index = frame.var_index;
+            frame.values.sort_by_key(|value| -self.contribution(solver.csp, var_index, *value));
+        }
+    }
+
+    fn contribution(&self, csp: &CSP, var_index: VariableIndex, value: Value) -> i64 {
+        self.sign * csp.objective_contribution(self.objective, var_index, value)
+    }
+
+    fn score(&self, csp: &CSP, assignment: &Assignment) -> i64 {
+        assignment.iter().enumerate().map(|(var_index, value)| self.contribution(csp, var_index, *value)).sum()
+    }
+
+    // The score of the assigned variables, and every unassigned magnet adds at most the best score
+    // among the values left in its domain that still fit under the clues given the current pole
+    // counts
+    fn upper_bound(&self, csp: &CSP, domains: &Domain, assignment: &Assignment) -> i64 {
+        let mut bound = 0;
+        for (var_index, value) in assignment.iter().enumerate() {
+            bound += if *value != Value::Unassigned {
+                self.contribution(csp, var_index, *value)
+            } else {
+                domains[var_index]
+                    .iter()
+                    .filter(|value| self.fits_counts(csp, var_index, **value))
+                    .map(|value| self.contribution(csp, var_index, *value))
+                    .max()
+                    .unwrap_or(0)
+            };
+        }
+        bound
+    }
+
+    fn fits_counts(&self, csp: &CSP, var_index: VariableIndex, value: Value) -> bool {
+        let poles = &csp.variables[var_index].poles;
+        let (positive, negative) = match value {
+            Value::Pole1PositivePole2Negative => (&poles[0], &poles[1]),
+            Value::Pole2PositivePole1Negative => (&poles[1], &poles[0]),
+            _ => return true,
+        };
+        let fits = |clue: Clue| !CSP::exceeds_clue(csp.current_count(clue) + 1, csp.clue_value(clue));
+        fits(Clue::RowPositive(positive.row))
+            && fits(Clue::RowNegative(negative.row))
+            && fits(Clue::ColPositive(positive.col))
+            && fits(Clue::ColNegative(negative.col))
+    }
+}
+
+// Parses an objective written as magnets, region:row,col,row,col for the rectangle between two
+// corners, or weights:<path> for a file with one line of whitespace separated weights per row
+pub fn parse_objective(text: &str) -> Result<Objective, String> {
+    let (name, argument) = text.split_once(':').unwrap_or((text, ""));
+    match name {
+        "magnets" => Ok(Objective::NonEmptyMagnets),
+        "region" => {
+            let corners: Vec<usize> = argument
+                .split(',')
+                .map(|part| part.trim().parse::<usize>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("Bad region '{}': {}", argument, e))?;
+            if corners.len() != 4 {
+                return Err(format!("Region '{}' must be written as row,col,row,col", argument));
+            }
+            let from = Point { row: corners[0].min(corners[2]), col: corners[1].min(corners[3]) };
+            let to = Point { row: corners[0].max(corners[2]), col: corners[1].max(corners[3]) };
+            Ok(Objective::PositiveCellsInRegion(from, to))
+        }
+        "weights" => {
+            let text = std::fs::read_to_string(argument).map_err(|e| format!("Couldn't read '{}': {}", argument, e))?;
+            let weights = text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.split_whitespace().map(|weight| weight.parse::<i64>()).collect::<Result<Vec<_>, _>>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("Bad weight in '{}': {}", argument, e))?;
+            Ok(Objective::CellWeights(weights))
+        }
+        _ => Err(format!("Unknown objective '{}', expected magnets, region:... or weights:...", text)),
+    }
+}
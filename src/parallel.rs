@@ -0,0 +1,230 @@
+use crate::csp::*;
+use crate::solver::Progress;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Condvar, Mutex};
+
+// Steps a worker runs between looking at the cancel flag and at idle workers
+const STEPS_BETWEEN_CHECKS: usize = 1000;
+
+// State shared by the workers of a parallel search. A subproblem is the root domains of an
+// independent search, and the subproblems of all workers never share a solution.
+struct WorkPool {
+    // one deque per worker, the owner takes from the back and the others steal from the front
+    queues: Vec<Mutex<VecDeque<Domain>>>,
+    // subproblems queued or being searched, the search is over when it drops to 0
+    pending: AtomicUsize,
+    // workers without a subproblem, a busy worker splits its search when this is not 0
+    idle: AtomicUsize,
+    found: AtomicUsize,
+    limit: Option<usize>,
+    // only the first solution is wanted
+    first_only: bool,
+    cancel: AtomicBool,
+    // idle workers sleep on wakeup until a subproblem is queued or the search ends, signal is
+    // held while checking for that so no wakeup is missed
+    signal: Mutex<()>,
+    wakeup: Condvar,
+}
+
+impl CSP {
+    // Searches with several threads and returns the first solution any of them finds
+    pub fn solve_parallel(&self, threads: usize) -> Option<Assignment> {
+        let (sender, receiver) = channel();
+        self.search_parallel(threads, Some(1), true, sender);
+        receiver.try_iter().next()
+    }
+
+    // Counts the solutions with several threads, stopping at `limit` if given
+    pub fn count_parallel(&self, threads: usize, limit: Option<usize>) -> usize {
+        let (sender, receiver) = channel::<Assignment>();
+        drop(receiver);
+        self.search_parallel(threads, limit, false, sender)
+    }
+
+    // Calls on_solution on the calling thread for every solution the workers find, in the order
+    // they are found, and returns their number
+    pub fn enumerate_parallel(&self, threads: usize, limit: Option<usize>, mut on_solution: impl FnMut(Assignment)) -> usize {
+        let (sender, receiver) = channel();
+        std::thread::scope(|scope| {
+            let search = scope.spawn(|| self.search_parallel(threads, limit, false, sender));
+            for solution in receiver {
+                on_solution(solution);
+            }
+            search.join().expect("A search thread panicked")
+        })
+    }
+
+    // Every worker owns a clone of the CSP, so the board and the pole counts are never shared.
+    // The whole search starts as a single subproblem, and whenever a worker is idle a busy one
+    // gives away the untried values of its shallowest level, which splits the tree on the first
+    // variables MRV picked.
+    fn search_parallel(&self, threads: usize, limit: Option<usize>, first_only: bool, sender: Sender<Assignment>) -> usize {
+        let threads = threads.max(1);
+        let pool = WorkPool {
+            queues: (0..threads).map(|_| Mutex::new(VecDeque::new())).collect(),
+            pending: AtomicUsize::new(1),
+            idle: AtomicUsize::new(0),
+            found: AtomicUsize::new(0),
+            limit,
+            first_only,
+            cancel: AtomicBool::new(false),
+            signal: Mutex::new(()),
+            wakeup: Condvar::new(),
+        };
+        pool.queues[0].lock().unwrap().push_back(self.initial_domain());
+
+        std::thread::scope(|scope| {
+            for worker in 0..threads {
+                let mut csp = self.clone();
+                let pool = &pool;
+                let sender = sender.clone();
+                scope.spawn(move || pool.work(worker, &mut csp, &sender));
+            }
+        });
+        let found = pool.found.load(Ordering::SeqCst);
+        limit.map_or(found, |limit| found.min(limit))
+    }
+}
+
+impl WorkPool {
+    fn work(&self, worker: usize, csp: &mut CSP, sender: &Sender<Assignment>) {
+        let mut waiting = false;
+        while !self.cancel.load(Ordering::SeqCst) {
+            match self.take(worker) {
+                Some(domain) => {
+                    if waiting {
+                        self.idle.fetch_sub(1, Ordering::SeqCst);
+                        waiting = false;
+                    }
+                    self.search(worker, csp, domain, sender);
+                    if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                        self.notify();
+                    }
+                }
+                None => {
+                    if self.pending.load(Ordering::SeqCst) == 0 {
+                        break;
+                    }
+                    if !waiting {
+                        self.idle.fetch_add(1, Ordering::SeqCst);
+                        waiting = true;
+                    }
+                    self.wait_for_work();
+                }
+            }
+        }
+        if waiting {
+            self.idle.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    // Blocks until a subproblem is queued, the last one is done or the search is cancelled
+    fn wait_for_work(&self) {
+        let mut guard = self.signal.lock().unwrap();
+        while self.pending.load(Ordering::SeqCst) > 0
+            && !self.cancel.load(Ordering::SeqCst)
+            && self.queues.iter().all(|queue| queue.lock().unwrap().is_empty())
+        {
+            guard = self.wakeup.wait(guard).unwrap();
+        }
+    }
+
+    fn notify(&self) {
+        let _guard = self.signal.lock().unwrap();
+        self.wakeup.notify_all();
+    }
+
+    fn stop(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        self.notify();
+    }
+
+    // Own subproblems first, newest first, then the oldest subproblem of another worker
+    fn take(&self, worker: usize) -> Option<Domain> {
+        if let Some(domain) = self.queues[worker].lock().unwrap().pop_back() {
+            return Some(domain);
+        }
+        let threads = self.queues.len();
+        (1..threads).find_map(|offset| self.queues[(worker + offset) % threads].lock().unwrap().pop_front())
+    }
+
+    fn search(&self, worker: usize, csp: &mut CSP, domain: Domain, sender: &Sender<Assignment>) {
+        let mut solutions = csp.solutions_from(domain, None);
+        loop {
+            match solutions.advance(Some(STEPS_BETWEEN_CHECKS)) {
+                Progress::Solution(solution) => {
+                    let previous = self.found.fetch_add(1, Ordering::SeqCst);
+                    if self.limit.is_some_and(|limit| previous >= limit) {
+                        self.stop();
+                        return;
+                    }
+                    // the receiver is gone when only counting
+                    let _ = sender.send(solution);
+                    if self.first_only || self.limit.is_some_and(|limit| previous + 1 >= limit) {
+                        self.stop();
+                        return;
+                    }
+                }
+                Progress::Paused => {}
+                // the workers run without limits, cancel stops them instead
+                Progress::Exhausted | Progress::LimitReached(_) => return,
+            }
+            if self.cancel.load(Ordering::SeqCst) {
+                return;
+            }
+            if self.idle.load(Ordering::SeqCst) > 0 && self.queues[worker].lock().unwrap().is_empty() {
+                let subproblems = solutions.solver.split_off();
+                self.pending.fetch_add(subproblems.len(), Ordering::SeqCst);
+                if !subproblems.is_empty() {
+                    self.queues[worker].lock().unwrap().extend(subproblems);
+                    self.notify();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_puzzles::*;
+
+    // The subproblems split off between workers cover every solution exactly once, however many
+    // workers share the search
+    #[test]
+    fn parallel_search_finds_each_solution_once() {
+        for backjumping in [false, true] {
+            let mut csp = puzzle(COLUMN_CLUES_ONLY, InferenceMode::FC);
+            csp.backjumping = backjumping;
+            let mut expected: Vec<Assignment> = csp.solutions(None).collect();
+            expected.sort_by_key(|solution| solution.iter().map(|value| *value as u8).collect::<Vec<u8>>());
+            for threads in [1, 2, 3, 4, 8] {
+                assert_eq!(csp.count_parallel(threads, None), csp.count_solutions(None), "{} threads", threads);
+                let mut found = Vec::new();
+                csp.enumerate_parallel(threads, None, |solution| found.push(solution));
+                found.sort_by_key(|solution| solution.iter().map(|value| *value as u8).collect::<Vec<u8>>());
+                assert_eq!(found, expected, "{} threads with backjumping {}", threads, backjumping);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_count_stops_at_the_limit() {
+        let csp = puzzle(COLUMN_CLUES_ONLY, InferenceMode::MAC);
+        for threads in [1, 3, 8] {
+            assert_eq!(csp.count_parallel(threads, Some(50)), 50);
+        }
+    }
+
+    #[test]
+    fn solve_parallel_returns_a_solution() {
+        for threads in [1, 2, 4] {
+            let csp = puzzle(TEST_CASE_1, InferenceMode::MAC);
+            let solution = csp.solve_parallel(threads).expect("Test case 1 has a solution");
+            assert!(csp.is_solution(&solution));
+            assert_eq!(puzzle(NO_SOLUTION, InferenceMode::MAC).solve_parallel(threads), None);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// Minimal RGB raster canvas with a self-contained PNG encoder.
+// The image data is stored in uncompressed deflate blocks so no compression library is needed.
+
+pub type Rgb = [u8; 3];
+
+pub const WHITE: Rgb = [255, 255, 255];
+pub const BLACK: Rgb = [0, 0, 0];
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+
+// 5x7 bitmaps, one byte per row, the lowest 5 bits are the pixels from left to right
+fn glyph(c: char) -> Option<[u8; GLYPH_HEIGHT]> {
+    match c {
+        '0' => Some([0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+        '1' => Some([0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+        '2' => Some([0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+        '3' => Some([0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+        '4' => Some([0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+        '5' => Some([0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+        '6' => Some([0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+        '7' => Some([0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+        '8' => Some([0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+        '9' => Some([0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+        '+' => Some([0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+        '-' => Some([0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+        '?' => Some([0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+        _ => None,
+    }
+}
+
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize, background: Rgb) -> Canvas {
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for _ in 0..width * height {
+            pixels.extend_from_slice(&background);
+        }
+        Canvas {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            let offset = (y * self.width + x) * 3;
+            self.pixels[offset..offset + 3].copy_from_slice(&color);
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Rgb) {
+        for py in y..(y + height).min(self.height) {
+            for px in x..(x + width).min(self.width) {
+                self.set_pixel(px, py, color);
+            }
+        }
+    }
+
+    // Draws the text centered on (center_x, center_y), each glyph pixel is scale x scale pixels.
+    // Characters without a glyph are drawn as blank space.
+    pub fn draw_text(&mut self, center_x: usize, center_y: usize, text: &str, scale: usize, color: Rgb) {
+        let advance = (GLYPH_WIDTH + 1) * scale;
+        let char_count = text.chars().count();
+        if char_count == 0 {
+            return;
+        }
+        let text_width = char_count * advance - scale;
+        let text_height = GLYPH_HEIGHT * scale;
+        let left = center_x.saturating_sub(text_width / 2);
+        let top = center_y.saturating_sub(text_height / 2);
+        for (i, c) in text.chars().enumerate() {
+            if let Some(rows) = glyph(c) {
+                for (row, bits) in rows.iter().enumerate() {
+                    for col in 0..GLYPH_WIDTH {
+                        if bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0 {
+                            self.fill_rect(left + i * advance + col * scale, top + row * scale, scale, scale, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn encode_png(&self) -> Vec<u8> {
+        let mut png: Vec<u8> = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bit depth, truecolor, deflate, adaptive filtering, no interlace
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_chunk(&mut png, b"IHDR", &header);
+
+        // every scanline starts with the filter type byte, 0 means no filtering
+        let row_len = self.width * 3;
+        let mut raw = Vec::with_capacity((row_len + 1) * self.height);
+        for y in 0..self.height {
+            raw.push(0);
+            raw.extend_from_slice(&self.pixels[y * row_len..(y + 1) * row_len]);
+        }
+        write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    pub fn save_png(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.encode_png())
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Wraps the data in a zlib stream made of stored (uncompressed) deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 65535;
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_last = blocks.peek().is_none();
+        out.push(if is_last { 1 } else { 0 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,151 @@
+use crate::csp::*;
+use crate::limits::*;
+use crate::restarts::RestartPolicy;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+
+// One way of running the search, the portfolio runs several of them side by side
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PortfolioConfig {
+    pub inference_mode: InferenceMode,
+    // random tie-breaking, see CSP::seed
+    pub seed: Option<u64>,
+    pub restart_policy: RestartPolicy,
+    pub backjumping: bool,
+}
+
+// Result of the configuration that finished first
+#[derive(Debug, Clone)]
+pub struct PortfolioOutcome {
+    pub winner: PortfolioConfig,
+    pub result: SolveResult,
+}
+
+impl PortfolioConfig {
+    pub fn new(inference_mode: InferenceMode) -> PortfolioConfig {
+        PortfolioConfig {
+            inference_mode,
+            seed: None,
+            restart_policy: RestartPolicy::Never,
+            backjumping: false,
+        }
+    }
+
+    // The three inference modes as they are, FC with backjumping, and randomized runs with
+    // restarts of the two that prune, which escape the bad early decisions a fixed order can get
+    // stuck below
+    pub fn defaults() -> Vec<PortfolioConfig> {
+        let randomized = |inference_mode, seed, restart_policy| PortfolioConfig {
+            seed: Some(seed),
+            restart_policy,
+            ..PortfolioConfig::new(inference_mode)
+        };
+        vec![
+            PortfolioConfig::new(InferenceMode::None),
+            PortfolioConfig::new(InferenceMode::FC),
+            PortfolioConfig::new(InferenceMode::MAC),
+            PortfolioConfig {
+                backjumping: true,
+                ..PortfolioConfig::new(InferenceMode::FC)
+            },
+            randomized(InferenceMode::FC, 1, RestartPolicy::Luby(100)),
+            randomized(InferenceMode::MAC, 2, RestartPolicy::Luby(100)),
+            randomized(InferenceMode::MAC, 3, RestartPolicy::Geometric(100, 1.5)),
+        ]
+    }
+}
+
+impl CSP {
+    // Runs every configuration on its own copy of the puzzle in its own thread, the first one to
+    // find a solution or to prove there is none cancels the others. If every configuration stops
+    // at a limit the first one is reported, None only when there are no configurations. The cancel
+    // token of the limits is shared with the runs when given, so it stops the whole portfolio, and
+    // it is set once a configuration finishes.
+    pub fn solve_portfolio(&self, configs: &[PortfolioConfig], limits: &SolveLimits) -> Option<PortfolioOutcome> {
+        let cancel = limits.cancel.clone().unwrap_or_else(|| Arc::new(AtomicBool::new(false)));
+        let limits = SolveLimits {
+            cancel: Some(cancel.clone()),
+            ..limits.clone()
+        };
+        let (sender, receiver) = channel();
+        std::thread::scope(|scope| {
+            for (index, config) in configs.iter().enumerate() {
+                let mut csp = self.clone();
+                let (limits, cancel, sender) = (&limits, &cancel, sender.clone());
+                scope.spawn(move || {
+                    csp.inference_mode = config.inference_mode;
+                    csp.restart_policy = config.restart_policy;
+                    csp.backjumping = config.backjumping;
+                    // restarting a deterministic search would only repeat it
+                    csp.seed = match config.restart_policy {
+                        RestartPolicy::Never => config.seed,
+                        _ => config.seed.or(Some(0)),
+                    };
+                    let result = csp.solve(limits);
+                    if !matches!(result, SolveResult::LimitReached(..)) {
+                        cancel.store(true, Ordering::SeqCst);
+                    }
+                    // the receiver outlives the scope
+                    let _ = sender.send((index, result));
+                });
+            }
+        });
+        drop(sender);
+
+        // in the order the configurations finished, the ones cancelled by the winner come last
+        let outcomes: Vec<PortfolioOutcome> = receiver
+            .into_iter()
+            .map(|(index, result)| PortfolioOutcome {
+                winner: configs[index],
+                result,
+            })
+            .collect();
+        let finished = |outcome: &&PortfolioOutcome| !matches!(outcome.result, SolveResult::LimitReached(..));
+        let stopped = |outcome: &&PortfolioOutcome| !matches!(outcome.result, SolveResult::LimitReached(Limit::Cancelled, _));
+        outcomes
+            .iter()
+            .find(finished)
+            .or_else(|| outcomes.iter().find(stopped))
+            .or(outcomes.first())
+            .cloned()
+    }
+}
+
+// Writes the configuration the way FromStr reads it, e.g. mac,cbj,seed=2,restarts=luby:100
+impl fmt::Display for PortfolioConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.inference_mode)?;
+        if self.backjumping {
+            write!(f, ",cbj")?;
+        }
+        if let Some(seed) = self.seed {
+            write!(f, ",seed={}", seed)?;
+        }
+        if self.restart_policy != RestartPolicy::Never {
+            write!(f, ",restarts={}", self.restart_policy)?;
+        }
+        Ok(())
+    }
+}
+
+// Parses an inference mode optionally followed by ,cbj for backjumping, ,seed=<n> and
+// ,restarts=<policy>
+impl std::str::FromStr for PortfolioConfig {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<PortfolioConfig, String> {
+        let mut parts = text.split(',');
+        let mut config = PortfolioConfig::new(parts.next().unwrap_or_default().parse()?);
+        for part in parts {
+            match part.split_once('=') {
+                None if part == "cbj" => config.backjumping = true,
+                Some(("seed", seed)) => config.seed = Some(seed.parse().map_err(|_| format!("Bad seed '{}' in configuration '{}'", seed, text))?),
+                Some(("restarts", policy)) => config.restart_policy = policy.parse()?,
+                _ => return Err(format!("Unknown setting '{}' in configuration '{}', expected cbj, seed=<n> or restarts=<policy>", part, text)),
+            }
+        }
+        Ok(config)
+    }
+}
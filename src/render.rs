@@ -0,0 +1,122 @@
+use crate::csp::*;
+use crate::png::*;
+
+pub const CELL_SIZE: usize = 32;
+const PADDING: usize = 8;
+const GLYPH_SCALE: usize = 2;
+const GRID_COLOR: Rgb = [200, 200, 200];
+pub const POSITIVE_COLOR: Rgb = [190, 30, 30];
+pub const NEGATIVE_COLOR: Rgb = [30, 60, 190];
+const OUTLINE_WIDTH: usize = 3;
+
+impl CSP {
+    // Returns the index of the variable covering each cell, cells that are not part of any magnet
+    // slot map to None
+    pub fn cell_owners(&self) -> Vec<Vec<Option<VariableIndex>>> {
+        let mut owners = vec![vec![None; self.col_size]; self.row_size];
+        for variable in &self.variables {
+            for pole in &variable.poles {
+                owners[pole.row][pole.col] = Some(variable.index);
+            }
+        }
+        owners
+    }
+
+    // Top left pixel of a board cell, leaving room for two rows and two columns of clues
+    pub fn cell_origin(&self, row: usize, col: usize) -> (usize, usize) {
+        (
+            PADDING + (2 + col) * CELL_SIZE,
+            PADDING + (2 + row) * CELL_SIZE,
+        )
+    }
+
+    // Draws the same content as print_board: column clues on top, row clues on the left, the grid
+    // with the outline of every magnet slot and the signs of the assigned cells.
+    pub fn render_board(&self, board: &[Vec<BoardCell>]) -> Canvas {
+        self.render_board_with(board, &|_, _| None)
+    }
+
+    // Like render_board, but every cell for which `background` returns a color is filled first
+    pub fn render_board_with(&self, board: &[Vec<BoardCell>], background: &dyn Fn(usize, usize) -> Option<Rgb>) -> Canvas {
+        let width = 2 * PADDING + (2 + self.col_size) * CELL_SIZE;
+        let height = 2 * PADDING + (2 + self.row_size) * CELL_SIZE;
+        let mut canvas = Canvas::new(width, height, WHITE);
+
+        for i in 0..self.row_size {
+            for j in 0..self.col_size {
+                if let Some(color) = background(i, j) {
+                    let (x, y) = self.cell_origin(i, j);
+                    canvas.fill_rect(x, y, CELL_SIZE, CELL_SIZE, color);
+                }
+            }
+        }
+
+        // clues
+        for j in 0..self.col_size {
+            let (x, _) = self.cell_origin(0, j);
+            let center_x = x + CELL_SIZE / 2;
+            canvas.draw_text(center_x, PADDING + CELL_SIZE / 2, &clue_text(self.col_pos_poles[j]), GLYPH_SCALE, POSITIVE_COLOR);
+            canvas.draw_text(center_x, PADDING + CELL_SIZE + CELL_SIZE / 2, &clue_text(self.col_neg_poles[j]), GLYPH_SCALE, NEGATIVE_COLOR);
+        }
+        for i in 0..self.row_size {
+            let (_, y) = self.cell_origin(i, 0);
+            let center_y = y + CELL_SIZE / 2;
+            canvas.draw_text(PADDING + CELL_SIZE / 2, center_y, &clue_text(self.row_pos_poles[i]), GLYPH_SCALE, POSITIVE_COLOR);
+            canvas.draw_text(PADDING + CELL_SIZE + CELL_SIZE / 2, center_y, &clue_text(self.row_neg_poles[i]), GLYPH_SCALE, NEGATIVE_COLOR);
+        }
+
+        // thin grid lines
+        let (left, top) = self.cell_origin(0, 0);
+        let grid_width = self.col_size * CELL_SIZE;
+        let grid_height = self.row_size * CELL_SIZE;
+        for i in 0..=self.row_size {
+            canvas.fill_rect(left, top + i * CELL_SIZE, grid_width + 1, 1, GRID_COLOR);
+        }
+        for j in 0..=self.col_size {
+            canvas.fill_rect(left + j * CELL_SIZE, top, 1, grid_height + 1, GRID_COLOR);
+        }
+
+        // thick outlines wherever two neighboring cells belong to different magnet slots
+        let owners = self.cell_owners();
+        for i in 0..self.row_size {
+            for j in 0..self.col_size {
+                let (x, y) = self.cell_origin(i, j);
+                let owner = owners[i][j];
+                if i == 0 || owners[i - 1][j] != owner {
+                    canvas.fill_rect(x, y, CELL_SIZE + 1, OUTLINE_WIDTH, BLACK);
+                }
+                if i + 1 == self.row_size {
+                    canvas.fill_rect(x, y + CELL_SIZE + 1 - OUTLINE_WIDTH, CELL_SIZE + 1, OUTLINE_WIDTH, BLACK);
+                }
+                if j == 0 || owners[i][j - 1] != owner {
+                    canvas.fill_rect(x, y, OUTLINE_WIDTH, CELL_SIZE + 1, BLACK);
+                }
+                if j + 1 == self.col_size {
+                    canvas.fill_rect(x + CELL_SIZE + 1 - OUTLINE_WIDTH, y, OUTLINE_WIDTH, CELL_SIZE + 1, BLACK);
+                }
+            }
+        }
+
+        // signs
+        for (i, row) in board.iter().enumerate() {
+            for (j, cell) in row.iter().enumerate() {
+                let (x, y) = self.cell_origin(i, j);
+                let (sign, color) = match cell {
+                    BoardCell::Positive => ("+", POSITIVE_COLOR),
+                    BoardCell::Negative => ("-", NEGATIVE_COLOR),
+                    _ => continue,
+                };
+                canvas.draw_text(x + CELL_SIZE / 2, y + CELL_SIZE / 2, sign, GLYPH_SCALE + 1, color);
+            }
+        }
+        canvas
+    }
+}
+
+fn clue_text(clue: i32) -> String {
+    if clue < 0 {
+        String::from("?")
+    } else {
+        clue.to_string()
+    }
+}
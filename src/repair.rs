@@ -0,0 +1,308 @@
+use crate::assumptions::CellAssumption;
+use crate::bigint::BigUint;
+use crate::clues::Clue;
+use crate::csp::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    // the clue gets the new value, an unknown clue can be set as well
+    Clue(Clue, i32),
+    // an extra given cell
+    Given(CellAssumption),
+}
+
+#[derive(Debug, Clone)]
+pub struct Repair {
+    pub edits: Vec<Edit>,
+    // the solution of the repaired puzzle, unique for ambiguous puzzles
+    pub solution: Assignment,
+}
+
+impl CSP {
+    // Proposes the smallest sets of edits, at most `max_edits` each, that make a puzzle without
+    // solution solvable: the fewest clue changes. For an ambiguous puzzle it proposes every single
+    // clue change and the fewest extra givens that make the solution unique. Returns no repairs
+    // for a puzzle that already has a unique solution or if no repair within the budget exists.
+    pub fn repair_suggestions(&mut self, max_edits: usize, max_suggestions: usize) -> Vec<Repair> {
+        let count = self.count_models();
+        let mut repairs = if count.is_zero() {
+            self.repair_unsolvable(max_edits)
+        } else if count == BigUint::from(1) {
+            Vec::new()
+        } else {
+            self.repair_ambiguous(max_edits)
+        };
+        let change = |repair: &Repair| -> i32 {
+            repair
+                .edits
+                .iter()
+                .map(|edit| match edit {
+                    Edit::Clue(clue, value) => (value - self.clue_value(*clue).max(0)).abs(),
+                    Edit::Given(_) => 0,
+                })
+                .sum()
+        };
+        repairs.sort_by_key(|repair| (repair.edits.len(), change(repair)));
+        repairs.truncate(max_suggestions);
+        repairs
+    }
+
+    // Every fix has to change a clue of the unsatisfiable core, so only sets of clues meeting the
+    // core are tried, smallest first. When the rest has a solution with the set dropped (made
+    // unknown), every combination of new values for the set is checked.
+    fn repair_unsolvable(&mut self, max_edits: usize) -> Vec<Repair> {
+        let core = match self.unsat_clue_core() {
+            Some(core) => core,
+            None => return Vec::new(),
+        };
+        let clues = self.clues();
+        let mut repairs: Vec<Repair> = Vec::new();
+        for size in 1..=max_edits.min(clues.len()) {
+            for subset in combinations(clues.len(), size) {
+                let changed: Vec<Clue> = subset.iter().map(|index| clues[*index]).collect();
+                if !changed.iter().any(|clue| core.contains(clue)) {
+                    continue;
+                }
+                let original: Vec<i32> = changed.iter().map(|clue| self.clue_value(*clue)).collect();
+                changed.iter().for_each(|clue| self.set_clue_value(*clue, -1));
+                if self.is_satisfiable() {
+                    repairs.extend(self.clue_changes(&changed, &original));
+                }
+                changed.iter().zip(&original).for_each(|(clue, value)| self.set_clue_value(*clue, *value));
+            }
+            if !repairs.is_empty() {
+                break;
+            }
+        }
+        repairs
+    }
+
+    // Every assignment of new values to the clues that makes the puzzle solvable, a clue that
+    // keeps its value would mean a smaller set of edits already works
+    fn clue_changes(&mut self, clues: &[Clue], original: &[i32]) -> Vec<Repair> {
+        let mut repairs = Vec::new();
+        let mut values: Vec<i32> = vec![0; clues.len()];
+        loop {
+            if values.iter().zip(original).all(|(value, original)| value != original) {
+                clues.iter().zip(&values).for_each(|(clue, value)| self.set_clue_value(*clue, *value));
+                if self.is_satisfiable() {
+                    if let Some(solution) = self.solutions(Some(1)).next() {
+                        let edits = clues.iter().zip(&values).map(|(clue, value)| Edit::Clue(*clue, *value)).collect();
+                        repairs.push(Repair { edits, solution });
+                    }
+                }
+            }
+            // advance the values like the digits of a counter
+            let mut i = 0;
+            while i < clues.len() && values[i] == self.max_clue_value(clues[i]) {
+                values[i] = 0;
+                i += 1;
+            }
+            if i == clues.len() {
+                break;
+            }
+            values[i] += 1;
+        }
+        clues.iter().for_each(|clue| self.set_clue_value(*clue, -1));
+        repairs
+    }
+
+    // A line never holds more poles of one sign than half of its cells, rounded up
+    fn max_clue_value(&self, clue: Clue) -> i32 {
+        let line_length = match clue {
+            Clue::RowPositive(_) | Clue::RowNegative(_) => self.col_size,
+            Clue::ColPositive(_) | Clue::ColNegative(_) => self.row_size,
+        };
+        line_length.div_ceil(2) as i32
+    }
+
+    fn repair_ambiguous(&mut self, max_edits: usize) -> Vec<Repair> {
+        if max_edits == 0 {
+            return Vec::new();
+        }
+        let mut repairs = self.unique_by_clue_change();
+        repairs.extend(self.unique_by_givens(max_edits));
+        repairs
+    }
+
+    // Single clue changes, including setting an unknown clue, after which the solution is unique
+    fn unique_by_clue_change(&mut self) -> Vec<Repair> {
+        let mut clues = Vec::new();
+        for i in 0..self.row_size {
+            clues.extend([Clue::RowPositive(i), Clue::RowNegative(i)]);
+        }
+        for j in 0..self.col_size {
+            clues.extend([Clue::ColPositive(j), Clue::ColNegative(j)]);
+        }
+
+        let mut repairs = Vec::new();
+        for clue in clues {
+            let original = self.clue_value(clue);
+            for value in 0..=self.max_clue_value(clue) {
+                if value == original {
+                    continue;
+                }
+                self.set_clue_value(clue, value);
+                if self.count_models() == BigUint::from(1) {
+                    if let Some(solution) = self.solutions(Some(1)).next() {
+                        repairs.push(Repair {
+                            edits: vec![Edit::Clue(clue, value)],
+                            solution,
+                        });
+                    }
+                }
+            }
+            self.set_clue_value(clue, original);
+        }
+        repairs
+    }
+
+    // Breadth first search over sets of givens, a given fixes the value of one magnet slot. Two
+    // solutions of the puzzle with the current givens can't both survive, so every larger set that
+    // leads to a unique solution holds a given that rules out one of them. Branching on those
+    // givens alone reaches every smallest set, all of them are returned.
+    fn unique_by_givens(&mut self, max_edits: usize) -> Vec<Repair> {
+        let mut repairs = Vec::new();
+        let mut frontier: Vec<Vec<CellAssumption>> = vec![Vec::new()];
+        let mut seen: Vec<Vec<CellAssumption>> = Vec::new();
+        for _ in 0..max_edits {
+            let mut next = Vec::new();
+            for givens in &frontier {
+                let witnesses = self.solutions_with_givens(givens, 2);
+                if witnesses.len() < 2 {
+                    continue;
+                }
+                let mut branches = Vec::new();
+                for variable in &self.variables {
+                    let pole1 = &variable.poles[0];
+                    if givens.iter().any(|(point, _)| point == pole1) {
+                        continue;
+                    }
+                    for value in [Value::Pole1PositivePole2Negative, Value::Pole2PositivePole1Negative, Value::Empty] {
+                        if witnesses.iter().all(|witness| witness[variable.index] == value) {
+                            continue;
+                        }
+                        let cell = match value {
+                            Value::Pole1PositivePole2Negative => BoardCell::Positive,
+                            Value::Pole2PositivePole1Negative => BoardCell::Negative,
+                            _ => BoardCell::Empty,
+                        };
+                        branches.push((pole1.clone(), cell));
+                    }
+                }
+
+                for given in branches {
+                    let mut candidate = givens.clone();
+                    candidate.push(given);
+                    candidate.sort_by_key(|(point, _)| (point.row, point.col));
+                    if seen.contains(&candidate) {
+                        continue;
+                    }
+                    seen.push(candidate.clone());
+
+                    let solutions = self.solutions_with_givens(&candidate, 2);
+                    if solutions.len() == 1 {
+                        repairs.push(Repair {
+                            edits: candidate.into_iter().map(Edit::Given).collect(),
+                            solution: solutions[0].clone(),
+                        });
+                    } else if solutions.len() > 1 {
+                        next.push(candidate);
+                    }
+                }
+            }
+            if !repairs.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        repairs
+    }
+
+    fn solutions_with_givens(&mut self, givens: &[CellAssumption], limit: usize) -> Vec<Assignment> {
+        let mut domain = self.initial_domain();
+        for (point, cell) in givens {
+            if !self.restrict_domain(&mut domain, point, cell) {
+                return Vec::new();
+            }
+        }
+        self.solutions_from(domain, Some(limit)).collect()
+    }
+
+    // Prints every suggestion as the clue vectors it changes, with changed entries shown as
+    // old/new and unknown clues as ?, followed by the extra givens and the solution it leads to,
+    // drawn with the edited clues
+    pub fn print_repairs(&self, repairs: &[Repair]) {
+        let text = |value: i32| if value < 0 { String::from("?") } else { value.to_string() };
+        for (number, repair) in repairs.iter().enumerate() {
+            let edit_word = if repair.edits.len() == 1 { "edit" } else { "edits" };
+            println!("Suggestion {}, {} {}", number + 1, repair.edits.len(), edit_word);
+            let vectors: [(&str, Vec<Clue>); 4] = [
+                ("row +", (0..self.row_size).map(Clue::RowPositive).collect()),
+                ("row -", (0..self.row_size).map(Clue::RowNegative).collect()),
+                ("column +", (0..self.col_size).map(Clue::ColPositive).collect()),
+                ("column -", (0..self.col_size).map(Clue::ColNegative).collect()),
+            ];
+            let new_value = |clue: &Clue| {
+                repair.edits.iter().find_map(|edit| match edit {
+                    Edit::Clue(edited, value) if edited == clue => Some(*value),
+                    _ => None,
+                })
+            };
+            for (name, vector) in vectors {
+                if !vector.iter().any(|clue| new_value(clue).is_some()) {
+                    continue;
+                }
+                print!("    {:10}", name);
+                for clue in &vector {
+                    let old = text(self.clue_value(*clue));
+                    match new_value(clue) {
+                        Some(value) => print!(" {}/{}", old, text(value)),
+                        None => print!(" {}", old),
+                    }
+                }
+                println!();
+            }
+            for edit in &repair.edits {
+                if let Edit::Given((point, cell)) = edit {
+                    println!("    given ({}, {}) {:?}", point.row, point.col, cell);
+                }
+            }
+            println!();
+            let board = self.board_from_assignment(&repair.solution);
+            let clue_text = |clue: Clue| text(new_value(&clue).unwrap_or(self.clue_value(clue)));
+            let cell_text = |i: usize, j: usize| match board[i][j] {
+                BoardCell::Positive => String::from("+"),
+                BoardCell::Negative => String::from("-"),
+                BoardCell::Empty => String::new(),
+                BoardCell::Unassigned => String::from("*"),
+            };
+            self.print_grid(&clue_text, &cell_text);
+            println!();
+        }
+    }
+}
+
+// All the sorted subsets of 0..n with k elements, in lexicographic order
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    let mut subsets = Vec::new();
+    let mut subset: Vec<usize> = (0..k).collect();
+    if k > n {
+        return subsets;
+    }
+    loop {
+        subsets.push(subset.clone());
+        // advance the rightmost element that still has room
+        let mut i = k;
+        while i > 0 && subset[i - 1] == n - k + i - 1 {
+            i -= 1;
+        }
+        if i == 0 {
+            return subsets;
+        }
+        subset[i - 1] += 1;
+        for j in i..k {
+            subset[j] = subset[j - 1] + 1;
+        }
+    }
+}
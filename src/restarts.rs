@@ -0,0 +1,73 @@
+// When to abandon the current search tree and start over from the root. The thresholds count
+// backtracks since the last restart, and restarts only help when ties are broken randomly, so
+// every run explores a different tree.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    // unit times the Luby sequence 1 1 2 1 1 2 4 1 1 2 1 1 2 4 8 ...
+    Luby(usize),
+    // first, then first * factor, first * factor^2 ...
+    Geometric(usize, f64),
+}
+
+impl RestartPolicy {
+    // Backtracks allowed before the restart with the given number, None when never restarting
+    pub fn threshold(&self, restart: usize) -> Option<usize> {
+        match *self {
+            RestartPolicy::Never => None,
+            RestartPolicy::Luby(unit) => Some(unit.saturating_mul(luby(restart + 1))),
+            RestartPolicy::Geometric(first, factor) => Some((first as f64 * factor.powi(restart as i32)).min(usize::MAX as f64) as usize),
+        }
+    }
+}
+
+// The i-th element (from 1) of the Luby sequence
+pub fn luby(i: usize) -> usize {
+    let mut i = i;
+    loop {
+        // smallest k with i <= 2^k - 1
+        let mut k = 1;
+        while (1usize << k) - 1 < i {
+            k += 1;
+        }
+        if i == (1usize << k) - 1 {
+            return 1usize << (k - 1);
+        }
+        i -= (1usize << (k - 1)) - 1;
+    }
+}
+
+// Parses never, luby[:unit] or geometric[:first[:factor]], the thresholds must be at least 1 and
+// the factor at least 1 so the search keeps making progress
+impl std::str::FromStr for RestartPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<RestartPolicy, String> {
+        let parts: Vec<&str> = text.split(':').collect();
+        let number = |index: usize, default: &str| -> Result<f64, String> {
+            let part = parts.get(index).copied().unwrap_or(default);
+            part.parse::<f64>()
+                .ok()
+                .filter(|value| *value >= 1.0)
+                .ok_or_else(|| format!("Bad number '{}' in restart policy '{}'", part, text))
+        };
+        match parts[0] {
+            "never" | "none" => Ok(RestartPolicy::Never),
+            "luby" => Ok(RestartPolicy::Luby(number(1, "1000")? as usize)),
+            "geometric" => Ok(RestartPolicy::Geometric(number(1, "1000")? as usize, number(2, "1.5")?)),
+            _ => Err(format!("Unknown restart policy '{}', expected never, luby[:unit] or geometric[:first[:factor]]", text)),
+        }
+    }
+}
+
+// Writes the policy the way FromStr reads it
+impl std::fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RestartPolicy::Never => write!(f, "never"),
+            RestartPolicy::Luby(unit) => write!(f, "luby:{}", unit),
+            RestartPolicy::Geometric(first, factor) => write!(f, "geometric:{}:{}", first, factor),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::csp::*;
+use crate::limits::SolveLimits;
+use crate::solver::{Progress, Solver};
+
+// Verdict on the number of solutions of a puzzle, a well formed puzzle is Unique
+#[derive(Debug, Clone)]
+pub enum Uniqueness {
+    None,
+    Unique(Assignment),
+    // two different solutions witnessing the ambiguity
+    Multiple(Assignment, Assignment),
+}
+
+// Iterator over all the solutions of a CSP. The search runs on the stack machine of Solver, so it
+// is suspended after each solution and resumed on the next call.
+pub struct Solutions<'a> {
+    pub solver: Solver<'a>,
+    pub limit: Option<usize>,
+    pub found: usize,
+}
+
+impl CSP {
+    // Returns an iterator over the solutions, stopping after `limit` solutions if given
+    pub fn solutions(&mut self, limit: Option<usize>) -> Solutions<'_> {
+        let initial_domain = self.initial_domain();
+        self.solutions_from(initial_domain, limit)
+    }
+
+    // Like solutions, but the search starts from the given domains instead of the full ones
+    pub fn solutions_from(&mut self, initial_domain: Domain, limit: Option<usize>) -> Solutions<'_> {
+        Solutions {
+            solver: self.solver_from(initial_domain),
+            limit,
+            found: 0,
+        }
+    }
+
+    // Counts the solutions, stopping as soon as `limit` of them have been found
+    pub fn count_solutions(&mut self, limit: Option<usize>) -> usize {
+        self.solutions(limit).count()
+    }
+
+    // Stops the search as soon as a second solution is found
+    pub fn uniqueness(&mut self) -> Uniqueness {
+        let mut solutions = self.solutions(Some(2));
+        match (solutions.next(), solutions.next()) {
+            (None, _) => Uniqueness::None,
+            (Some(solution), None) => Uniqueness::Unique(solution),
+            (Some(first), Some(second)) => Uniqueness::Multiple(first, second),
+        }
+    }
+}
+
+impl Solutions<'_> {
+    // Shuffles the value order at every level with the given seed, so the first solution found is
+    // a random one
+    pub fn randomized(mut self, seed: u64) -> Self {
+        self.solver = self.solver.randomized(seed);
+        self
+    }
+
+    // Stops the search once any of the limits is reached. They bound the whole run, counted from
+    // the start of the search or the resumed checkpoint.
+    pub fn within(mut self, limits: SolveLimits) -> Self {
+        self.solver.limits = limits;
+        self
+    }
+
+    // Runs the search until the next solution, or for at most `max_steps` steps if given
+    pub fn advance(&mut self, max_steps: Option<usize>) -> Progress {
+        if self.limit.is_some_and(|limit| self.found >= limit) {
+            return Progress::Exhausted;
+        }
+        let progress = self.solver.run_for(max_steps.unwrap_or(usize::MAX));
+        if let Progress::Solution(_) = progress {
+            self.found += 1;
+        }
+        progress
+    }
+}
+
+impl Iterator for Solutions<'_> {
+    type Item = Assignment;
+
+    fn next(&mut self) -> Option<Assignment> {
+        match self.advance(None) {
+            Progress::Solution(solution) => Some(solution),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use crate::backjumping::{blame, Explanations};
+use crate::csp::*;
+use crate::limits::{Limit, SearchBudget, SolveLimits};
+use crate::rng::Rng;
+
+// One level of the search: the variable chosen at this depth, its ordered values and the length
+// of the trail when it was chosen, undoing the trail back to it restores the domains of that time
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub var_index: VariableIndex,
+    pub values: Vec<Value>,
+    // position of the next value to try
+    pub next_value: usize,
+    pub trail_mark: usize,
+    // value currently assigned at this depth, undone before the next one is tried
+    pub assigned: Option<Value>,
+    // with backjumping: the earlier variables blamed for the values of this one that failed and
+    // whether a solution was found below this depth
+    pub conflict_set: Vec<VariableIndex>,
+    pub solution_below: bool,
+}
+
+// What a single step of the search did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    // the value was assigned and propagated, the search went one level deeper
+    Decision(VariableIndex, Value),
+    // the value was rejected by the consistency check or by inference
+    Conflict(VariableIndex, Value),
+    // every value of the variable was tried, the search went one level up
+    Backtrack(VariableIndex),
+    // every value of the first variable was tried, the search went back to the second one, the
+    // deepest variable to blame, skipping the levels in between
+    Backjump(VariableIndex, VariableIndex),
+    // the assignment is complete, stepping again continues with the next solution
+    Solution(Assignment),
+    // the whole search space was explored
+    Exhausted,
+}
+
+// Outcome of running the search for a bounded number of steps
+#[derive(Debug, Clone)]
+pub enum Progress {
+    Solution(Assignment),
+    // the steps ran out, running again continues where the search stopped
+    Paused,
+    // every solution was found or the number of solutions asked for was reached
+    Exhausted,
+    // a limit of the search was reached before the search space was exhausted
+    LimitReached(Limit),
+}
+
+// Backtracking search as an explicit stack machine. Every call to step does one unit of work, so
+// the search can be driven incrementally, inspected between steps and never recurses.
+pub struct Solver<'a> {
+    pub csp: &'a mut CSP,
+    pub assignment: Assignment,
+    pub stack: Vec<Frame>,
+    // domains of the root until the search starts
+    pub initial_domain: Option<Domain>,
+    // the one copy of the domains, pruned by inference as the search goes deeper and restored
+    // from the trail of removals as it comes back
+    pub domains: Domain,
+    // with backjumping: the variables blamed for the values missing from every domain, kept on
+    // the same trail as the domains
+    pub explanations: Explanations,
+    pub trail: Trail,
+    // breaks the ties of MRV and LCV when set, see CSP::seed
+    pub rng: Option<Rng>,
+    // try the values of every variable in random order instead of LCV order, needs rng
+    pub shuffle_values: bool,
+    // values tried and values undone so far
+    pub nodes: usize,
+    pub backtracks: usize,
+    // checked by run_for after every step
+    pub limits: SolveLimits,
+}
+
+impl CSP {
+    pub fn solver(&mut self) -> Solver<'_> {
+        let initial_domain = self.initial_domain();
+        self.solver_from(initial_domain)
+    }
+
+    // Like solver, but the search starts from the given domains instead of the full ones
+    pub fn solver_from(&mut self, initial_domain: Domain) -> Solver<'_> {
+        self.reset();
+        Solver {
+            assignment: vec![Value::Unassigned; self.variables.len()],
+            rng: self.seed.map(Rng::new),
+            csp: self,
+            stack: Vec::new(),
+            initial_domain: Some(initial_domain),
+            domains: Vec::new(),
+            explanations: Vec::new(),
+            trail: Vec::new(),
+            shuffle_values: false,
+            nodes: 0,
+            backtracks: 0,
+            limits: SolveLimits::default(),
+        }
+    }
+}
+
+impl Solver<'_> {
+    // Shuffles the value order at every level with the given seed, so the first solution found is
+    // a random one
+    pub fn randomized(mut self, seed: u64) -> Self {
+        self.rng = Some(Rng::new(seed));
+        self.shuffle_values = true;
+        self
+    }
+
+    // Number of variables assigned on the search path
+    pub fn depth(&self) -> usize {
+        self.stack.iter().filter(|frame| frame.assigned.is_some()).count()
+    }
+
+    pub fn assignment(&self) -> &Assignment {
+        &self.assignment
+    }
+
+    // Domains the next decision is taken from, None once the search is over
+    pub fn domains(&self) -> Option<&Domain> {
+        match self.initial_domain {
+            Some(_) => self.initial_domain.as_ref(),
+            None if self.stack.is_empty() => None,
+            None => Some(&self.domains),
+        }
+    }
+
+    pub fn step(&mut self) -> Step {
+        // first call: set up the root of the search
+        if self.initial_domain.is_some() {
+            if self.csp.is_complete(&self.assignment) {
+                self.initial_domain = None;
+                return Step::Solution(self.assignment.clone());
+            }
+            self.start();
+        }
+
+        let frame = match self.stack.last_mut() {
+            Some(frame) => frame,
+            None => return Step::Exhausted,
+        };
+        // undo the value tried last time at this depth
+        if let Some(value) = frame.assigned.take() {
+            self.csp.unassign(value, frame.var_index, &mut self.assignment);
+            self.backtracks += 1;
+        }
+        if frame.next_value == frame.values.len() {
+            if self.csp.backjumping {
+                return self.backjump();
+            }
+            let var_index = frame.var_index;
+            self.stack.pop();
+            self.undo_to_top();
+            return Step::Backtrack(var_index);
+        }
+        let value = frame.values[frame.next_value];
+        frame.next_value += 1;
+        self.nodes += 1;
+
+        let var_index = frame.var_index;
+        if !self.csp.assign(value, var_index, &mut self.assignment) {
+            return Step::Conflict(var_index, value);
+        }
+        frame.assigned = Some(value);
+        if !self.csp.is_consistent(var_index) {
+            if self.csp.backjumping {
+                for culprit in self.csp.inconsistency_culprits(var_index, &self.assignment) {
+                    blame(&mut frame.conflict_set, culprit);
+                }
+            }
+            return Step::Conflict(var_index, value);
+        }
+        if self.csp.backjumping {
+            if let Err(culprits) = self.csp.explained_inference(var_index, &mut self.domains, &mut self.explanations, &mut self.trail, &self.assignment) {
+                for culprit in culprits.into_iter().filter(|culprit| *culprit != var_index) {
+                    blame(&mut frame.conflict_set, culprit);
+                }
+                self.undo_to_top();
+                return Step::Conflict(var_index, value);
+            }
+        } else if !self.csp.inference(var_index, &mut self.domains, &mut self.trail, &self.assignment) {
+            self.undo_to_top();
+            return Step::Conflict(var_index, value);
+        }
+        if self.csp.is_complete(&self.assignment) {
+            for frame in &mut self.stack {
+                frame.solution_below = true;
+            }
+            self.undo_to_top();
+            return Step::Solution(self.assignment.clone());
+        }
+        self.push_frame();
+        Step::Decision(var_index, value)
+    }
+
+    // Runs at most `steps` steps, stopping early at a solution, when the search is over or once
+    // one of the limits is reached
+    pub fn run_for(&mut self, steps: usize) -> Progress {
+        let limits = self.limits.clone();
+        let mut budget = SearchBudget::new(&limits);
+        for _ in 0..steps {
+            let step = self.step();
+            let within_limits = budget.check(self.nodes, self.backtracks, 0);
+            match step {
+                Step::Solution(solution) => return Progress::Solution(solution),
+                Step::Exhausted => return Progress::Exhausted,
+                _ if !within_limits => return Progress::LimitReached(budget.reached.unwrap()),
+                _ => {}
+            }
+        }
+        Progress::Paused
+    }
+
+    // Puts the domains and explanations back as they were when the variable on top of the stack
+    // was chosen
+    fn undo_to_top(&mut self) {
+        let mark = self.stack.last().map_or(0, |frame| frame.trail_mark);
+        CSP::undo_trail(&mut self.domains, &mut self.explanations, &mut self.trail, mark);
+    }
+
+    // Sets up the root level of the search without trying a value yet, so its values can still be
+    // reordered. Does nothing once the search has started or when there is nothing to search.
+    pub fn start(&mut self) {
+        if self.csp.is_complete(&self.assignment) {
+            return;
+        }
+        if let Some(initial_domain) = self.initial_domain.take() {
+            self.explanations = if self.csp.backjumping { vec![Vec::new(); initial_domain.len()] } else { Vec::new() };
+            self.domains = initial_domain;
+            self.trail.clear();
+            self.push_frame();
+        }
+    }
+
+    // Rejects the value of the last decision after all, as if inference had failed on it: the
+    // level pushed for it is dropped with its pruning, and the next step tries the next value
+    pub fn cut(&mut self) {
+        if self.stack.last().is_some_and(|frame| frame.assigned.is_none()) {
+            self.stack.pop();
+            self.undo_to_top();
+        }
+    }
+
+    // Undoes every assignment and starts over from the given root domains. The generator keeps its
+    // state, so a randomized search takes other decisions this time.
+    pub fn restart(&mut self, initial_domain: Domain) {
+        while let Some(frame) = self.stack.pop() {
+            if let Some(value) = frame.assigned {
+                self.csp.unassign(value, frame.var_index, &mut self.assignment);
+            }
+        }
+        self.trail.clear();
+        self.initial_domain = Some(initial_domain);
+    }
+
+    // Gives away the untried values of the shallowest level that has some left, as root domains of
+    // independent searches. The levels below are fixed to their assigned values in those domains,
+    // and this search no longer tries the values given away, so together they cover the same
+    // solutions as before, each exactly once.
+    pub fn split_off(&mut self) -> Vec<Domain> {
+        let level = match self.stack.iter().position(|frame| frame.next_value < frame.values.len()) {
+            Some(level) => level,
+            None => return Vec::new(),
+        };
+        let mut domains = self.domains.clone();
+        for entry in self.trail[self.stack[level].trail_mark..].iter().rev() {
+            if let TrailEntry::Removal(removal) = entry {
+                CSP::restore_value(&mut domains, removal);
+            }
+        }
+        for frame in &self.stack[..level] {
+            if let Some(value) = frame.assigned {
+                domains[frame.var_index] = vec![value];
+            }
+        }
+        let frame = &mut self.stack[level];
+        let untried = frame.values.split_off(frame.next_value);
+        untried
+            .into_iter()
+            .map(|value| {
+                let mut subproblem = domains.clone();
+                subproblem[frame.var_index] = vec![value];
+                subproblem
+            })
+            .collect()
+    }
+
+    // Conflict-directed backjumping from the top level, whose values all failed: every earlier
+    // level up to the deepest variable in its conflict set is undone, and that variable inherits
+    // the rest of the set. The levels skipped can't help while that variable keeps its value.
+    // Once a solution was found below a level it only steps back one level, so enumerating the
+    // solutions skips none of them.
+    fn backjump(&mut self) -> Step {
+        let frame = self.stack.pop().expect("Backjumping from an empty stack");
+        let target = if frame.solution_below {
+            self.stack.len().checked_sub(1)
+        } else {
+            self.stack.iter().rposition(|level| frame.conflict_set.contains(&level.var_index))
+        };
+        let parent = self.stack.len().checked_sub(1);
+        // nothing to blame: the conflict follows from the root domains alone
+        let target = match target {
+            Some(target) => target,
+            None => {
+                self.undo_levels(0);
+                return Step::Backtrack(frame.var_index);
+            }
+        };
+        self.undo_levels(target + 1);
+        let level = &mut self.stack[target];
+        for culprit in frame.conflict_set {
+            if culprit != level.var_index {
+                blame(&mut level.conflict_set, culprit);
+            }
+        }
+        if Some(target) == parent {
+            Step::Backtrack(frame.var_index)
+        } else {
+            Step::Backjump(frame.var_index, level.var_index)
+        }
+    }
+
+    // Pops the levels from the given depth on, undoing their values and their pruning
+    fn undo_levels(&mut self, depth: usize) {
+        while self.stack.len() > depth {
+            if let Some(frame) = self.stack.pop() {
+                if let Some(value) = frame.assigned {
+                    self.csp.unassign(value, frame.var_index, &mut self.assignment);
+                    self.backtracks += 1;
+                }
+            }
+        }
+        self.undo_to_top();
+    }
+
+    // Pushes the next variable to branch on, chosen from the current domains
+    fn push_frame(&mut self) {
+        let domains = &self.domains;
+        let var_index = self.csp.select_unassigned_variable_with(domains, &self.assignment, self.rng.as_mut());
+        if let Some(var_index) = var_index {
+            let mut values = self.csp.order_domain_values_with(var_index, domains, &self.assignment, self.rng.as_mut());
+            if let Some(rng) = self.rng.as_mut().filter(|_| self.shuffle_values) {
+                rng.shuffle(&mut values);
+            }
+            let conflict_set = self.explanations.get(var_index).cloned().unwrap_or_default();
+            self.stack.push(Frame {
+                var_index,
+                values,
+                next_value: 0,
+                trail_mark: self.trail.len(),
+                assigned: None,
+                conflict_set,
+                solution_below: false,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_puzzles::*;
+
+    const MODES: [InferenceMode; 3] = [InferenceMode::None, InferenceMode::FC, InferenceMode::MAC];
+
+    fn solutions(text: &str, inference_mode: InferenceMode, backjumping: bool) -> Vec<Assignment> {
+        let mut csp = puzzle(text, inference_mode);
+        csp.backjumping = backjumping;
+        let mut solutions: Vec<Assignment> = csp.solutions(None).collect();
+        for solution in &solutions {
+            assert!(csp.is_solution(solution), "{:?} with backjumping {}", inference_mode, backjumping);
+        }
+        // in a fixed order, backjumping may find them in another one
+        solutions.sort_by_key(|solution| solution.iter().map(|value| *value as u8).collect::<Vec<u8>>());
+        solutions
+    }
+
+    // Backjumping skips levels, so it must not skip solutions: every mode finds the same ones,
+    // with and without it, and as many as the row sweep counter
+    #[test]
+    fn counts_agree_with_and_without_backjumping() {
+        for (text, count) in [(TEST_CASE_1, 2), (COLUMN_CLUES_ONLY, 271)] {
+            assert_eq!(puzzle(text, InferenceMode::MAC).count_models().to_string(), count.to_string());
+            let expected = solutions(text, InferenceMode::None, false);
+            assert_eq!(expected.len(), count);
+            for inference_mode in MODES {
+                for backjumping in [false, true] {
+                    let found = solutions(text, inference_mode, backjumping);
+                    assert_eq!(found, expected, "{:?} with backjumping {}", inference_mode, backjumping);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unsatisfiable_puzzle_is_exhausted_without_solutions() {
+        for inference_mode in MODES {
+            for backjumping in [false, true] {
+                assert!(solutions(NO_SOLUTION, inference_mode, backjumping).is_empty());
+                let mut csp = puzzle(NO_SOLUTION, inference_mode);
+                csp.backjumping = backjumping;
+                let mut solver = csp.solver();
+                assert!(matches!(solver.run_for(usize::MAX), Progress::Exhausted));
+                assert!(solver.stack.is_empty() && solver.trail.is_empty());
+            }
+        }
+    }
+
+    // Stopping every few steps and running again finds the same solutions as running through
+    #[test]
+    fn run_for_resumes_where_it_stopped() {
+        for backjumping in [false, true] {
+            let expected = solutions(COLUMN_CLUES_ONLY, InferenceMode::FC, backjumping);
+            let mut csp = puzzle(COLUMN_CLUES_ONLY, InferenceMode::FC);
+            csp.backjumping = backjumping;
+            let mut solver = csp.solver();
+            let mut found = Vec::new();
+            loop {
+                match solver.run_for(7) {
+                    Progress::Solution(solution) => found.push(solution),
+                    Progress::Paused => {}
+                    Progress::Exhausted => break,
+                    Progress::LimitReached(limit) => panic!("{}", limit),
+                }
+            }
+            found.sort_by_key(|solution| solution.iter().map(|value| *value as u8).collect::<Vec<u8>>());
+            assert_eq!(found, expected);
+        }
+    }
+}
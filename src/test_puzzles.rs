@@ -0,0 +1,44 @@
+use crate::csp::{InferenceMode, CSP};
+
+// Test case 1 of the README, it has 2 solutions
+pub const TEST_CASE_1: &str = "6 6
+1 2 3 1 2 1
+1 2 1 3 1 2
+2 1 2 2 2 1
+2 1 2 2 1 2
+1 0 0 1 0 0
+1 0 0 1 0 0
+1 0 0 0 0 1
+1 1 0 0 1 1
+1 1 0 0 1 1
+1 0 0 0 0 1";
+
+// Test case 1 with the row clues unknown, it has 271 solutions
+pub const COLUMN_CLUES_ONLY: &str = "6 6
+-1 -1 -1 -1 -1 -1
+-1 -1 -1 -1 -1 -1
+2 1 2 2 2 1
+2 1 2 2 1 2
+1 0 0 1 0 0
+1 0 0 1 0 0
+1 0 0 0 0 1
+1 1 0 0 1 1
+1 1 0 0 1 1
+1 0 0 0 0 1";
+
+// Test case 1 with a typo in the second row clue, it has no solution
+pub const NO_SOLUTION: &str = "6 6
+1 3 3 1 2 1
+1 2 1 3 1 2
+2 1 2 2 2 1
+2 1 2 2 1 2
+1 0 0 1 0 0
+1 0 0 1 0 0
+1 0 0 0 0 1
+1 1 0 0 1 1
+1 1 0 0 1 1
+1 0 0 0 0 1";
+
+pub fn puzzle(text: &str, inference_mode: InferenceMode) -> CSP {
+    crate::parse_problem(text, inference_mode).unwrap()
+}